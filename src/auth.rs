@@ -0,0 +1,137 @@
+///////////////////////////////////////////////////////////////////////////////
+// NAME:            auth.rs
+//
+// AUTHOR:          Ethan D. Twardy <ethan.twardy@gmail.com>
+//
+// DESCRIPTION:     HTTP Basic authentication, for sharing a dev server.
+//
+// CREATED:         10/15/2026
+//
+// LAST EDITED:     10/15/2026
+////
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use hyper::{Body, Request, Response, StatusCode};
+
+const CHALLENGE: &str = "Basic realm=\"dev-prox\", charset=\"UTF-8\"";
+
+// Compare two strings in time that depends only on their lengths, so that
+// the time taken to refuse a password doesn't tell how much of it was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b).fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+pub struct BasicAuth {
+    // Credentials like "user:password", as they're sent by the client.
+    credentials: Vec<String>,
+}
+
+impl BasicAuth {
+    // Each of `credentials` is a user and password, separated by a ':'.
+    pub fn new(credentials: Vec<String>) -> Result<Self, String> {
+        match credentials.iter().find(|entry| !entry.contains(':')) {
+            Some(entry) => Err(format!("expected USER:PASSWORD, not {:?}",
+                                       entry)),
+            None => Ok(Self { credentials }),
+        }
+    }
+
+    // Read credentials from a file, one per line. Blank lines and those
+    // beginning with '#' are skipped.
+    pub fn parse(text: &str) -> Vec<String> {
+        text.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string)
+            .collect()
+    }
+
+    // Whether the request carries any of the credentials. Every one is
+    // compared, whichever matches.
+    fn allows(&self, request: &Request<Body>) -> bool {
+        let given = request.headers().get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split_once(' '))
+            .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("basic"))
+            .and_then(|(_, encoded)| STANDARD.decode(encoded.trim()).ok());
+        match given {
+            Some(given) => self.credentials.iter()
+                .fold(false, |allowed, credentials| {
+                    constant_time_eq(credentials.as_bytes(), &given) | allowed
+                }),
+            None => false,
+        }
+    }
+
+    // Challenge a request without valid credentials. Any other request gets
+    // None, and is served as usual.
+    pub fn challenge(&self, request: &Request<Body>) ->
+        Option<Response<Body>>
+    {
+        match self.allows(request) {
+            true => None,
+            false => Some(Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header("www-authenticate", CHALLENGE)
+                .header("content-type", "text/plain")
+                .body(Body::from("401 Unauthorized\n"))
+                .unwrap()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(authorization: Option<&str>) -> Request<Body> {
+        let mut request = Request::get("/");
+        if let Some(authorization) = authorization {
+            request = request.header("authorization", authorization);
+        }
+        request.body(Body::empty()).unwrap()
+    }
+
+    fn basic(credentials: &str) -> String {
+        format!("Basic {}", STANDARD.encode(credentials))
+    }
+
+    #[test]
+    fn only_valid_credentials_are_allowed() {
+        let auth = BasicAuth::new(vec!["alice:secret".to_string(),
+                                       "bob:hunter2".to_string()]).unwrap();
+        assert!(auth.challenge(&request(Some(&basic("alice:secret"))))
+                .is_none());
+        assert!(auth.challenge(&request(Some(&basic("bob:hunter2"))))
+                .is_none());
+
+        for authorization in [None, Some(basic("alice:wrong")),
+                              Some(basic("alice:secret2")),
+                              Some("Bearer alice:secret".to_string()),
+                              Some("Basic !!!".to_string())]
+        {
+            let response = auth.challenge(&request(authorization.as_deref()))
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+            assert_eq!(response.headers()["www-authenticate"], CHALLENGE);
+        }
+    }
+
+    #[test]
+    fn credentials_need_a_user_and_password() {
+        assert!(BasicAuth::new(vec!["alice".to_string()]).is_err());
+        assert_eq!(BasicAuth::parse("# users\nalice:secret\n\n  bob:pw  \n"),
+                   ["alice:secret", "bob:pw"]);
+    }
+
+    #[test]
+    fn comparisons_need_equal_lengths() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret!"));
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
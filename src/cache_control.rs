@@ -0,0 +1,112 @@
+///////////////////////////////////////////////////////////////////////////////
+// NAME:            cache_control.rs
+//
+// AUTHOR:          Ethan D. Twardy <ethan.twardy@gmail.com>
+//
+// DESCRIPTION:     Rules for the Cache-Control header of static files.
+//
+// CREATED:         10/14/2026
+//
+// LAST EDITED:     10/14/2026
+////
+
+use std::path::Path;
+
+use serde::Deserialize;
+
+// Browsers revalidate every file, so edits always show up in development.
+pub const DEFAULT_CACHE_CONTROL: &str = "no-cache";
+
+// Match `text` against a glob `pattern`, where '*' matches any run of
+// characters within a path segment, "**" matches across segments, and '?'
+// matches any one character other than '/'.
+fn glob_matches(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', rest @ ..] => (0..=text.len())
+            .any(|skip| glob_matches(rest, &text[skip..])),
+        [b'*', rest @ ..] => (0..=text.len())
+            .take_while(|&skip| skip == 0 || text[skip - 1] != b'/')
+            .any(|skip| glob_matches(rest, &text[skip..])),
+        [b'?', rest @ ..] => match text {
+            [first, text @ ..] if *first != b'/' => glob_matches(rest, text),
+            _ => false,
+        },
+        [first, rest @ ..] => match text {
+            [character, text @ ..] if character == first =>
+                glob_matches(rest, text),
+            _ => false,
+        },
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CacheRule {
+    // A glob matched against the path of the file, relative to the root. A
+    // pattern without a '/' is matched against the file name alone.
+    pub pattern: String,
+    pub value: String,
+}
+
+// Match a path relative to the root against a glob. A pattern without a '/'
+// is matched against the file name alone.
+pub fn pattern_matches(pattern: &str, path: &Path) -> bool {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let text = match pattern.contains('/') {
+        true => path.as_str(),
+        false => path.rsplit('/').next().unwrap_or_default(),
+    };
+
+    glob_matches(pattern.trim_start_matches('/').as_bytes(), text.as_bytes())
+}
+
+impl CacheRule {
+    pub fn matches(&self, path: &Path) -> bool {
+        pattern_matches(&self.pattern, path)
+    }
+}
+
+// Find the Cache-Control value for a file. The first matching rule wins.
+pub fn cache_control<'a>(rules: &'a [CacheRule], path: &Path) -> &'a str {
+    rules.iter()
+        .find(|rule| rule.matches(path))
+        .map(|rule| rule.value.as_str())
+        .unwrap_or(DEFAULT_CACHE_CONTROL)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: &str, value: &str) -> CacheRule {
+        CacheRule { pattern: pattern.to_string(), value: value.to_string() }
+    }
+
+    #[test]
+    fn globs_match_within_and_across_segments() {
+        assert!(pattern_matches("*.js", Path::new("assets/app.3f9a.js")));
+        assert!(pattern_matches("assets/*.js", Path::new("assets/app.js")));
+        assert!(!pattern_matches("assets/*.js",
+                                 Path::new("assets/vendor/lib.js")));
+        assert!(pattern_matches("assets/**.js",
+                                Path::new("assets/vendor/lib.js")));
+        assert!(pattern_matches("/app.?.js", Path::new("app.1.js")));
+        assert!(!pattern_matches("app.?.js", Path::new("app.12.js")));
+    }
+
+    #[test]
+    fn the_first_matching_rule_wins() {
+        let rules = [
+            rule("*.*.js", "max-age=31536000, immutable"),
+            rule("*.js", "max-age=60"),
+        ];
+        assert_eq!(cache_control(&rules, Path::new("app.3f9a.js")),
+                   "max-age=31536000, immutable");
+        assert_eq!(cache_control(&rules, Path::new("app.js")), "max-age=60");
+        assert_eq!(cache_control(&rules, Path::new("index.html")),
+                   DEFAULT_CACHE_CONTROL);
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
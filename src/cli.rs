@@ -0,0 +1,167 @@
+///////////////////////////////////////////////////////////////////////////////
+// NAME:            cli.rs
+//
+// AUTHOR:          Ethan D. Twardy <ethan.twardy@gmail.com>
+//
+// DESCRIPTION:     Command line arguments, which override the config file.
+//
+// CREATED:         10/14/2026
+//
+// LAST EDITED:     10/14/2026
+////
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use clap::Parser;
+use log::LevelFilter;
+
+use crate::config::{Config, ForwardedStyle, RouteConfig};
+
+// Parse a route given as PREFIX=UPSTREAM.
+fn parse_route(route: &str) -> Result<RouteConfig, String> {
+    let (prefix, upstream) = route.split_once('=')
+        .ok_or_else(|| "expected PREFIX=UPSTREAM".to_string())?;
+    if prefix.is_empty() {
+        return Err("missing prefix".to_string());
+    } else if upstream.is_empty() {
+        return Err("missing upstream URI".to_string());
+    }
+
+    let upstream = upstream.parse()
+        .map_err(|error| format!("invalid upstream URI: {}", error))?;
+    Ok(RouteConfig {
+        prefix: prefix.to_string(), pattern: None, exact: false,
+        trailing_slash: false, upstream: vec![upstream],
+        compression: true, timeout_ms: None, delay_ms: None, retries: 0,
+        retry_backoff_ms: None,
+        strip_prefix: true, preserve_prefix: false, replace_prefix: None,
+        rewrites: Vec::new(),
+        add_request_headers: Vec::new(), add_response_headers: Vec::new(),
+        preserve_host: false, host_header: None,
+        forwarded: ForwardedStyle::default(),
+        ca_file: None, system_roots: true, danger_accept_invalid_certs: false,
+        client_cert: None, client_key: None,
+        error_rate: 0.0, error_status: 500, error_seed: None,
+    })
+}
+
+#[derive(Parser)]
+#[command(version, about = "A simple development server and reverse proxy")]
+pub struct Args {
+    #[arg(help = "Config file [default: ./dev-proxy.toml, if it exists]")]
+    pub config: Option<PathBuf>,
+
+    #[arg(long, value_name = "ADDRESS", help = "Address to listen on")]
+    pub bind: Option<SocketAddr>,
+
+    #[arg(long, value_name = "PATH",
+          help = "Unix domain socket to listen on, in place of the address")]
+    pub unix: Option<PathBuf>,
+
+    #[arg(long, value_name = "DIRECTORY",
+          help = "Directory to serve static files from")]
+    pub root: Option<PathBuf>,
+
+    #[arg(long = "proxy", value_name = "PREFIX=UPSTREAM",
+          value_parser = parse_route,
+          help = "Proxy requests under PREFIX to UPSTREAM (repeatable)")]
+    pub routes: Vec<RouteConfig>,
+
+    #[arg(long, value_name = "LEVEL",
+          help = "Most verbose level to log: off, error, warn, info, debug")]
+    pub log_level: Option<LevelFilter>,
+
+    #[arg(long, value_name = "FILE",
+          help = "Page to serve with 404 responses, relative to the root")]
+    pub not_found: Option<PathBuf>,
+
+    #[arg(long, help = "Serve files whose names begin with a '.'")]
+    pub allow_hidden: bool,
+
+    #[arg(long, help = "Don't compress responses")]
+    pub no_compression: bool,
+
+    #[arg(long, help = "Reload pages in the browser when static files change")]
+    pub live_reload: bool,
+
+    #[arg(long, help = "Respond 404 to /favicon.ico if the root has none")]
+    pub no_favicon: bool,
+
+    #[arg(long, help = "Accept PUT and DELETE requests for static files")]
+    pub allow_write: bool,
+}
+
+impl Args {
+    // Override the settings in `config` with those given on the command line.
+    pub fn apply(self, config: &mut Config) {
+        if let Some(bind) = self.bind {
+            config.bind = bind;
+        }
+
+        if let Some(unix) = self.unix {
+            config.unix = Some(unix);
+        }
+
+        if let Some(root) = self.root {
+            config.root = vec![root];
+        }
+
+        if let Some(log_level) = self.log_level {
+            config.log_level = log_level;
+        }
+
+        if let Some(not_found) = self.not_found {
+            config.not_found = Some(not_found);
+        }
+
+        config.routes.extend(self.routes);
+        config.allow_hidden |= self.allow_hidden;
+        config.live_reload |= self.live_reload;
+        config.allow_write |= self.allow_write;
+        if self.no_compression {
+            config.compression = false;
+        }
+
+        if self.no_favicon {
+            config.favicon = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_override_the_config() {
+        let args = Args::try_parse_from([
+            "dev-proxy", "--bind", "0.0.0.0:9000", "--root", "./public",
+            "--proxy", "/api=http://localhost:3000",
+            "--proxy", "/auth=http://localhost:4000",
+        ]).unwrap();
+        let mut config = Config::default();
+        args.apply(&mut config);
+        assert_eq!(config.bind, "0.0.0.0:9000".parse().unwrap());
+        assert_eq!(config.root, [PathBuf::from("./public")]);
+        let routes: Vec<(&str, String)> = config.routes.iter()
+            .map(|route| (route.prefix.as_str(),
+                          route.upstream[0].to_string()))
+            .collect();
+        assert_eq!(routes, [("/api", "http://localhost:3000/".to_string()),
+                            ("/auth", "http://localhost:4000/".to_string())]);
+    }
+
+    #[test]
+    fn malformed_flags_are_usage_errors() {
+        for flag in [["--bind", "localhost"], ["--proxy", "/api"],
+                     ["--proxy", "/api="], ["--proxy", "=http://a"]]
+        {
+            let error = Args::try_parse_from(
+                ["dev-proxy", flag[0], flag[1]]).err().unwrap();
+            assert_eq!(error.exit_code(), 2, "{:?}", flag);
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
@@ -0,0 +1,245 @@
+///////////////////////////////////////////////////////////////////////////////
+// NAME:            compression.rs
+//
+// AUTHOR:          Ethan D. Twardy <ethan.twardy@gmail.com>
+//
+// DESCRIPTION:     Content-Encoding negotiation and on-the-fly compression.
+//
+// CREATED:         10/14/2026
+//
+// LAST EDITED:     10/14/2026
+////
+
+use std::io;
+
+use async_compression::tokio::bufread::{
+    BrotliDecoder, BrotliEncoder, GzipDecoder, GzipEncoder, ZlibDecoder,
+    ZlibEncoder,
+};
+pub use async_compression::Level;
+use futures_util::TryStreamExt;
+use hyper::{
+    Body, Response, StatusCode,
+    header::{self, HeaderMap, HeaderValue},
+};
+use tokio_util::io::{ReaderStream, StreamReader};
+
+// Responses smaller than this aren't worth compressing.
+pub const MIN_COMPRESS_SIZE: u64 = 1024;
+
+// Compression is done on every request, so favor speed over ratio.
+pub const DEFAULT_LEVEL: Level = Level::Fastest;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+    Deflate,
+}
+
+// The encodings that files are compressed with on the fly, and that may be
+// found precompressed next to a file, each in order of preference.
+pub const ON_THE_FLY: &[Encoding] = &[
+    Encoding::Brotli, Encoding::Gzip, Encoding::Deflate,
+];
+pub const PRECOMPRESSED: &[Encoding] = &[Encoding::Brotli, Encoding::Gzip];
+
+impl Encoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Brotli => "br",
+            Self::Gzip => "gzip",
+            Self::Deflate => "deflate",
+        }
+    }
+
+    // Parse the value of a Content-Encoding header.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "br" => Some(Self::Brotli),
+            "gzip" | "x-gzip" => Some(Self::Gzip),
+            "deflate" => Some(Self::Deflate),
+            _ => None,
+        }
+    }
+
+    // The extension of a precompressed copy of a file.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Brotli => "br",
+            Self::Gzip => "gz",
+            Self::Deflate => "zz",
+        }
+    }
+}
+
+// Parse the quality value of one element of an Accept-Encoding header.
+fn quality(parameters: &str) -> f32 {
+    parameters.split(';')
+        .filter_map(|parameter| parameter.trim().strip_prefix("q="))
+        .find_map(|value| value.trim().parse().ok())
+        .unwrap_or(1.0)
+}
+
+// Choose the encoding of `candidates` that the client most prefers, if it
+// accepts any. Ties go to whichever comes first in `candidates`.
+pub fn negotiate(headers: &HeaderMap, candidates: &[Encoding]) ->
+    Option<Encoding>
+{
+    let mut wildcard = None;
+    let mut accepted = Vec::new();
+    for element in headers.get_all("accept-encoding").iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+    {
+        let (coding, parameters) = element.split_once(';')
+            .unwrap_or((element, ""));
+        let coding = coding.trim().to_ascii_lowercase();
+        let quality = quality(parameters);
+        match coding.as_str() {
+            "*" => wildcard = Some(quality),
+            "x-gzip" => accepted.push(("gzip".to_string(), quality)),
+            _ => accepted.push((coding, quality)),
+        }
+    }
+
+    let mut best: Option<(Encoding, f32)> = None;
+    for encoding in candidates {
+        let quality = accepted.iter()
+            .find(|(coding, _)| coding == encoding.name())
+            .map(|(_, quality)| *quality)
+            .or(wildcard)
+            .unwrap_or(0.0);
+        if quality > 0.0 && best.map_or(true, |(_, best)| quality > best) {
+            best = Some((*encoding, quality));
+        }
+    }
+
+    best.map(|(encoding, _)| encoding)
+}
+
+// Only textual formats benefit from compression. Images, video, fonts and
+// archives are almost always compressed already. Event streams are excluded,
+// since the encoder would hold events back until it had enough to compress.
+pub fn is_compressible(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+    (essence.starts_with("text/") && essence != "text/event-stream")
+        || matches!(essence, "application/javascript" | "application/json"
+                    | "application/xml" | "application/wasm"
+                    | "image/svg+xml")
+}
+
+// Compress a body as it's streamed.
+pub fn compress(body: Body, encoding: Encoding, level: Level) -> Body {
+    let reader = StreamReader::new(body.map_err(io::Error::other));
+    match encoding {
+        Encoding::Brotli => Body::wrap_stream(ReaderStream::new(
+            BrotliEncoder::with_quality(reader, level))),
+        Encoding::Gzip => Body::wrap_stream(ReaderStream::new(
+            GzipEncoder::with_quality(reader, level))),
+        Encoding::Deflate => Body::wrap_stream(ReaderStream::new(
+            ZlibEncoder::with_quality(reader, level))),
+    }
+}
+
+// Decompress a body as it's streamed.
+pub fn decompress(body: Body, encoding: Encoding) -> Body {
+    let reader = StreamReader::new(body.map_err(io::Error::other));
+    match encoding {
+        Encoding::Brotli => Body::wrap_stream(ReaderStream::new(
+            BrotliDecoder::new(reader))),
+        Encoding::Gzip => Body::wrap_stream(ReaderStream::new(
+            GzipDecoder::new(reader))),
+        Encoding::Deflate => Body::wrap_stream(ReaderStream::new(
+            ZlibDecoder::new(reader))),
+    }
+}
+
+// Compress a proxied response in `encoding`, unless it's already encoded, or
+// too small or of the wrong type to benefit. A response without a length may
+// be streamed a chunk at a time, like progress updates, so it's left alone
+// for the same reason as event streams.
+pub fn compress_response(response: Response<Body>, encoding: Option<Encoding>,
+                         level: Level) -> Response<Body>
+{
+    let headers = response.headers();
+    let compressible = response.status() == StatusCode::OK
+        && !headers.contains_key(header::CONTENT_ENCODING)
+        && headers.get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(is_compressible)
+        && headers.get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok())
+            .is_some_and(|length| length >= MIN_COMPRESS_SIZE);
+    if !compressible {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    parts.headers.append(
+        header::VARY, HeaderValue::from_static("accept-encoding"));
+    let encoding = match encoding {
+        Some(encoding) => encoding,
+        None => return Response::from_parts(parts, body),
+    };
+
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.insert(
+        header::CONTENT_ENCODING, HeaderValue::from_static(encoding.name()));
+    Response::from_parts(parts, compress(body, encoding, level))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_textual_types_are_compressible() {
+        for content_type in ["text/html; charset=utf-8", "text/css",
+                             "application/javascript", "application/json",
+                             "image/svg+xml"]
+        {
+            assert!(is_compressible(content_type), "{}", content_type);
+        }
+        for content_type in ["image/png", "video/mp4", "font/woff2",
+                             "text/event-stream", "application/zip"]
+        {
+            assert!(!is_compressible(content_type), "{}", content_type);
+        }
+    }
+
+    #[tokio::test]
+    async fn compressed_bodies_decompress_to_the_original() {
+        let original = "function f() { return 1; }\n".repeat(100);
+        for &encoding in ON_THE_FLY {
+            let body = compress(Body::from(original.clone()), encoding,
+                                DEFAULT_LEVEL);
+            let compressed = hyper::body::to_bytes(body).await.unwrap();
+            assert!(compressed.len() < original.len(), "{}", encoding.name());
+
+            let body = decompress(Body::from(compressed), encoding);
+            assert_eq!(hyper::body::to_bytes(body).await.unwrap(), original);
+        }
+    }
+
+    fn accepting(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("accept-encoding", HeaderValue::from_str(value)
+                       .unwrap());
+        headers
+    }
+
+    #[test]
+    fn brotli_is_preferred_unless_the_client_says_otherwise() {
+        let negotiated = |value| negotiate(&accepting(value), ON_THE_FLY);
+        assert!(negotiated("gzip, br") == Some(Encoding::Brotli));
+        assert!(negotiated("br;q=0.5, gzip;q=0.8") == Some(Encoding::Gzip));
+        assert!(negotiated("br;q=0, gzip") == Some(Encoding::Gzip));
+        assert!(negotiated("*;q=0.1, gzip;q=0") == Some(Encoding::Brotli));
+        assert!(negotiated("identity").is_none());
+        assert!(negotiate(&HeaderMap::new(), ON_THE_FLY).is_none());
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
@@ -0,0 +1,596 @@
+///////////////////////////////////////////////////////////////////////////////
+// NAME:            config.rs
+//
+// AUTHOR:          Ethan D. Twardy <ethan.twardy@gmail.com>
+//
+// DESCRIPTION:     Configuration file for the server.
+//
+// CREATED:         10/14/2026
+//
+// LAST EDITED:     10/14/2026
+////
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use hyper::{
+    Uri,
+    header::{HeaderName, HeaderValue},
+};
+use log::LevelFilter;
+use regex::Regex;
+use serde::{Deserialize, Deserializer, de};
+
+use crate::cache_control::CacheRule;
+
+pub const DEFAULT_CONFIG_FILE: &str = "dev-proxy.toml";
+
+///////////////////////////////////////////////////////////////////////////////
+// ConfigError
+////
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Tls(String),
+    Route(String),
+    Auth(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            Self::Io(path, error) =>
+                write!(f, "{}: {}", path.display(), error),
+            Self::Parse(path, error) =>
+                write!(f, "{}: {}", path.display(), error),
+            Self::Tls(message) => write!(f, "{}", message),
+            Self::Route(message) => write!(f, "route: {}", message),
+            Self::Auth(message) => write!(f, "basic_auth: {}", message),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Config
+////
+
+fn default_bind() -> SocketAddr {
+    "127.0.0.1:8080".parse().unwrap()
+}
+
+fn default_root() -> Vec<PathBuf> {
+    vec![PathBuf::from(".")]
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_log_level() -> LevelFilter {
+    LevelFilter::Info
+}
+
+fn default_error_status() -> u16 {
+    500
+}
+
+fn default_health_check() -> String {
+    "/healthz".to_string()
+}
+
+// A setting that may be given as one value, or a list of them.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+impl<T> OneOrMany<T> {
+    fn into_vec(self) -> Vec<T> {
+        match self {
+            Self::One(value) => vec![value],
+            Self::Many(values) => values,
+        }
+    }
+}
+
+// A route may have one upstream, or a list of them.
+fn deserialize_upstreams<'de, D>(deserializer: D) -> Result<Vec<Uri>, D::Error>
+where D: Deserializer<'de>,
+{
+    let upstreams = OneOrMany::<String>::deserialize(deserializer)?
+        .into_vec();
+    if upstreams.is_empty() {
+        return Err(de::Error::custom("a route needs at least one upstream"));
+    }
+
+    upstreams.iter()
+        .map(|upstream| upstream.parse().map_err(de::Error::custom))
+        .collect()
+}
+
+// Patterns are compiled as the config is loaded, so that a bad one is caught
+// then.
+fn deserialize_regex<'de, D>(deserializer: D) -> Result<Regex, D::Error>
+where D: Deserializer<'de>,
+{
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&pattern).map_err(de::Error::custom)
+}
+
+fn deserialize_pattern<'de, D>(deserializer: D) ->
+    Result<Option<Regex>, D::Error>
+where D: Deserializer<'de>,
+{
+    deserialize_regex(deserializer).map(Some)
+}
+
+// Static files may be served from one root, or overlaid from a list of them.
+fn deserialize_roots<'de, D>(deserializer: D) -> Result<Vec<PathBuf>, D::Error>
+where D: Deserializer<'de>,
+{
+    let roots = OneOrMany::<PathBuf>::deserialize(deserializer)?.into_vec();
+    match roots.is_empty() {
+        true => Err(de::Error::custom("at least one root is needed")),
+        false => Ok(roots),
+    }
+}
+
+// A table of header values by name.
+fn deserialize_headers<'de, D>(deserializer: D) ->
+    Result<Vec<(HeaderName, HeaderValue)>, D::Error>
+where D: Deserializer<'de>,
+{
+    HashMap::<String, String>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(name, value)| {
+            let name = HeaderName::from_bytes(name.as_bytes());
+            match (name, HeaderValue::from_str(&value)) {
+                (Ok(name), Ok(value)) => Ok((name, value)),
+                (Err(error), _) => Err(de::Error::custom(error)),
+                (_, Err(error)) => Err(de::Error::custom(error)),
+            }
+        })
+        .collect()
+}
+
+// How symlinks under the root are treated when serving static files.
+#[derive(Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SymlinkPolicy {
+    // Follow symlinks wherever they lead.
+    Follow,
+    // Follow symlinks only if their target is also under the root.
+    #[default]
+    SameRoot,
+    // Refuse any path that passes through a symlink.
+    Deny,
+}
+
+// How a proxied request tells the upstream where it came from.
+#[derive(Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ForwardedStyle {
+    // X-Forwarded-For, X-Forwarded-Proto and X-Forwarded-Host.
+    #[default]
+    XForwarded,
+    // The standard Forwarded header (RFC 7239).
+    Forwarded,
+    // Nothing at all.
+    None,
+}
+
+// Rewrites the path and query of a request on its way to the upstream, if
+// `pattern` matches them. The replacement may refer to the pattern's groups.
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RewriteRule {
+    #[serde(deserialize_with = "deserialize_regex")]
+    pub pattern: Regex,
+    pub replacement: String,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteConfig {
+    // Paths are matched either by prefix, or by a regular expression.
+    #[serde(default)]
+    pub prefix: String,
+    #[serde(default, deserialize_with = "deserialize_pattern")]
+    pub pattern: Option<Regex>,
+
+    // Match only the prefix itself, rather than the paths under it, and if
+    // `trailing_slash`, the prefix followed by a '/' too.
+    #[serde(default)]
+    pub exact: bool,
+    #[serde(default)]
+    pub trailing_slash: bool,
+
+    // Requests are spread across the upstreams round-robin.
+    #[serde(deserialize_with = "deserialize_upstreams")]
+    pub upstream: Vec<Uri>,
+
+    // Whether responses from this route may be compressed on the fly.
+    #[serde(default = "default_true")]
+    pub compression: bool,
+
+    // How long to wait for the upstream to respond to each attempt, in
+    // milliseconds.
+    pub timeout_ms: Option<u64>,
+
+    // How long to wait before sending each request to the upstream, in
+    // milliseconds, to test how an app behaves on a slow network.
+    pub delay_ms: Option<u64>,
+
+    // How many times GET and HEAD requests are retried when the upstream
+    // can't be reached, and how long to wait before the first retry, in
+    // milliseconds.
+    #[serde(default)]
+    pub retries: u32,
+    pub retry_backoff_ms: Option<u64>,
+
+    // Whether the prefix is removed from the path before it's appended to the
+    // upstream URI. Either `strip_prefix = false` or `preserve_prefix = true`
+    // keeps it.
+    #[serde(default = "default_true")]
+    pub strip_prefix: bool,
+    #[serde(default)]
+    pub preserve_prefix: bool,
+
+    // A path to put in place of the prefix. This overrides `strip_prefix`.
+    pub replace_prefix: Option<String>,
+
+    // Rules rewriting the path, tried in order. The first that matches is used
+    // in place of `strip_prefix` and `replace_prefix`.
+    #[serde(default, rename = "rewrite")]
+    pub rewrites: Vec<RewriteRule>,
+
+    // Headers set on requests to the upstream, and on its responses,
+    // replacing any with the same name.
+    #[serde(default, deserialize_with = "deserialize_headers")]
+    pub add_request_headers: Vec<(HeaderName, HeaderValue)>,
+    #[serde(default, deserialize_with = "deserialize_headers")]
+    pub add_response_headers: Vec<(HeaderName, HeaderValue)>,
+
+    // The Host header sent to the upstream is the authority of its URI,
+    // unless the client's is preserved, or another is given.
+    #[serde(default)]
+    pub preserve_host: bool,
+    pub host_header: Option<String>,
+
+    // The headers saying where requests came from.
+    #[serde(default)]
+    pub forwarded: ForwardedStyle,
+
+    // A PEM file of CA certificates that https upstreams are verified
+    // against, along with the system's roots unless `system_roots` is false.
+    // Or any certificate at all can be accepted, for self-signed ones.
+    pub ca_file: Option<PathBuf>,
+    #[serde(default = "default_true")]
+    pub system_roots: bool,
+    #[serde(default)]
+    pub danger_accept_invalid_certs: bool,
+
+    // PEM files of a certificate chain and its private key, presented to
+    // upstreams that ask the client to authenticate.
+    pub client_cert: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
+
+    // The share of requests (between 0 and 1) that fail on purpose, without
+    // reaching the upstream, the status they fail with, and a seed that makes
+    // the same requests fail every time.
+    #[serde(default)]
+    pub error_rate: f32,
+    #[serde(default = "default_error_status")]
+    pub error_status: u16,
+    pub error_seed: Option<u64>,
+}
+
+// A directory of static files served under a prefix other than "/".
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MountConfig {
+    pub prefix: String,
+    #[serde(deserialize_with = "deserialize_roots")]
+    pub root: Vec<PathBuf>,
+
+    // Index file names for this mount, in place of the top-level ones.
+    pub index: Option<Vec<String>>,
+}
+
+// Routes and directories served for requests to one host name, in place of
+// the top-level ones.
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HostConfig {
+    pub name: String,
+    // Without a root, only the routes and mounts of the host are served.
+    #[serde(default, deserialize_with = "deserialize_roots")]
+    pub root: Vec<PathBuf>,
+    #[serde(default, rename = "route")]
+    pub routes: Vec<RouteConfig>,
+
+    #[serde(default, rename = "mount")]
+    pub mounts: Vec<MountConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default = "default_bind")]
+    pub bind: SocketAddr,
+
+    // The path of a Unix domain socket to listen on, in place of `bind`.
+    pub unix: Option<PathBuf>,
+
+    // Files are served from the first of the roots that has them.
+    #[serde(default = "default_root", deserialize_with = "deserialize_roots")]
+    pub root: Vec<PathBuf>,
+
+    // The most verbose level of messages that are logged. Each request is
+    // logged at the info level.
+    #[serde(default = "default_log_level")]
+    pub log_level: LevelFilter,
+
+    // PEM files of the certificate chain and private key to serve https
+    // with. Plain http is served without them.
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+
+    #[serde(default, rename = "route")]
+    pub routes: Vec<RouteConfig>,
+
+    // A route for every request that no route, mount or file matches, with
+    // no prefix or pattern of its own.
+    pub catch_all: Option<RouteConfig>,
+
+    #[serde(default, rename = "mount")]
+    pub mounts: Vec<MountConfig>,
+
+    // Virtual hosts, with routes of their own.
+    #[serde(default, rename = "host")]
+    pub hosts: Vec<HostConfig>,
+
+    // Size in bytes above which static files are streamed from disk.
+    pub stream_threshold: Option<u64>,
+
+    // File (relative to the root) to serve for paths that don't exist.
+    pub fallback: Option<PathBuf>,
+
+    // Page (relative to the root) to serve with 404 responses.
+    pub not_found: Option<PathBuf>,
+
+    // Only serve the fallback (by default, the root index.html) for page
+    // navigations, so that missing assets still 404.
+    #[serde(default)]
+    pub spa: bool,
+
+    // Render listings of directories that have no index file.
+    #[serde(default)]
+    pub listing: bool,
+
+    // Include hidden files in directory listings.
+    #[serde(default)]
+    pub show_hidden: bool,
+
+    // Respond 404 rather than 403 to requests outside of the root.
+    #[serde(default)]
+    pub conceal_forbidden: bool,
+
+    // Serve files and directories whose names begin with a '.'.
+    #[serde(default)]
+    pub allow_hidden: bool,
+
+    #[serde(default)]
+    pub symlinks: SymlinkPolicy,
+
+    // The charset declared for text files, and overrides of it by extension.
+    // An empty charset declares none, for files that declare their own.
+    pub charset: Option<String>,
+    #[serde(default)]
+    pub charsets: HashMap<String, String>,
+
+    // MIME types by extension, overriding the built-in ones.
+    #[serde(default)]
+    pub mime_types: HashMap<String, String>,
+
+    // Compress textual files for clients that accept it.
+    #[serde(default = "default_true")]
+    pub compression: bool,
+
+    // The quality level passed to the encoder. Defaults to the fastest.
+    pub compression_level: Option<i32>,
+
+    // Origins allowed to make cross-origin requests ("*" for any), and
+    // whether they may send credentials.
+    #[serde(default)]
+    pub cors_origins: Vec<String>,
+    #[serde(default)]
+    pub cors_credentials: bool,
+
+    // Credentials like "user:password" that every request must carry, given
+    // directly or as a file with one on each line. Without any, requests
+    // aren't authenticated.
+    #[serde(default)]
+    pub basic_auth: Vec<String>,
+    pub basic_auth_file: Option<PathBuf>,
+
+    // A snippet of HTML to inject into pages, given directly or as a file,
+    // and whether to inject it into those from upstreams too.
+    pub inject: Option<String>,
+    pub inject_file: Option<PathBuf>,
+    #[serde(default)]
+    pub inject_proxied: bool,
+
+    // Globs of static files to substitute environment variables into, like
+    // "config.js", and the prefix of the names of the variables that may be
+    // substituted.
+    #[serde(default)]
+    pub substitute: Vec<String>,
+    pub substitute_prefix: Option<String>,
+
+    // Reload pages in the browser when static files change.
+    #[serde(default)]
+    pub live_reload: bool,
+
+    // Total size in bytes of the in-memory cache of small static files. They
+    // aren't cached without it, or if it's zero.
+    pub cache_size: Option<u64>,
+
+    // Rules for the Cache-Control header of static files.
+    #[serde(default, rename = "cache")]
+    pub cache_rules: Vec<CacheRule>,
+
+    // The names of the files served for directories, in order of preference.
+    pub index: Option<Vec<String>>,
+
+    // Serve a blank favicon.ico if the root doesn't have one.
+    #[serde(default = "default_true")]
+    pub favicon: bool,
+
+    // Accept PUT requests that upload files into the static directories, and
+    // DELETE requests that remove them, and the largest upload in bytes.
+    #[serde(default)]
+    pub allow_write: bool,
+    pub max_upload_size: Option<u64>,
+
+    // The path of the liveness probe. An empty path disables it.
+    #[serde(default = "default_health_check")]
+    pub health_check: String,
+
+    // Count requests, and serve the counts on /metrics.
+    #[serde(default)]
+    pub metrics: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind: default_bind(),
+            unix: None,
+            root: default_root(),
+            log_level: default_log_level(),
+            tls_cert: None,
+            tls_key: None,
+            routes: Vec::new(),
+            catch_all: None,
+            mounts: Vec::new(),
+            hosts: Vec::new(),
+            stream_threshold: None,
+            fallback: None,
+            not_found: None,
+            spa: false,
+            listing: false,
+            show_hidden: false,
+            conceal_forbidden: false,
+            allow_hidden: false,
+            symlinks: SymlinkPolicy::default(),
+            charset: None,
+            charsets: HashMap::new(),
+            mime_types: HashMap::new(),
+            compression: true,
+            compression_level: None,
+            cors_origins: Vec::new(),
+            cors_credentials: false,
+            basic_auth: Vec::new(),
+            basic_auth_file: None,
+            inject: None,
+            inject_file: None,
+            inject_proxied: false,
+            substitute: Vec::new(),
+            substitute_prefix: None,
+            live_reload: false,
+            cache_size: None,
+            cache_rules: Vec::new(),
+            favicon: true,
+            index: None,
+            allow_write: false,
+            max_upload_size: None,
+            health_check: default_health_check(),
+            metrics: false,
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let contents = fs::read_to_string(path)
+            .map_err(|error| ConfigError::Io(path.to_owned(), error))?;
+        toml::from_str(&contents)
+            .map_err(|error| ConfigError::Parse(path.to_owned(), error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_are_read_from_the_config() {
+        let config: Config = toml::from_str(r#"
+            bind = "0.0.0.0:3000"
+            root = "dist"
+
+            [[route]]
+            prefix = "/api"
+            upstream = "http://localhost:5000"
+
+            [[route]]
+            prefix = "/auth"
+            upstream = ["http://localhost:6000", "http://localhost:6001"]
+        "#).unwrap();
+        assert_eq!(config.bind, "0.0.0.0:3000".parse().unwrap());
+        assert_eq!(config.root, vec![PathBuf::from("dist")]);
+
+        let routes: Vec<(&str, Vec<String>)> = config.routes.iter()
+            .map(|route| (route.prefix.as_str(), route.upstream.iter()
+                          .map(|upstream| upstream.to_string()).collect()))
+            .collect();
+        assert_eq!(routes, [
+            ("/api", vec!["http://localhost:5000/".to_string()]),
+            ("/auth", vec!["http://localhost:6000/".to_string(),
+                           "http://localhost:6001/".to_string()]),
+        ]);
+    }
+
+    #[test]
+    fn everything_has_a_default() {
+        let config: Config = toml::from_str("").unwrap();
+        assert_eq!(config.bind, default_bind());
+        assert_eq!(config.root, default_root());
+        assert!(config.routes.is_empty());
+    }
+
+    #[test]
+    fn malformed_configs_are_refused() {
+        for config in ["bind = 8080", "[[route]]\nprefix = \"/api\"",
+                       "unknown = true"]
+        {
+            assert!(toml::from_str::<Config>(config).is_err(), "{}", config);
+        }
+    }
+
+    #[test]
+    fn missing_files_name_the_file() {
+        let path = Path::new("/nonexistent/dev-proxy.toml");
+        let error = Config::load(path).err().unwrap();
+        assert!(matches!(error, ConfigError::Io(..)));
+        assert!(error.to_string().starts_with("/nonexistent/dev-proxy.toml"));
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
@@ -0,0 +1,138 @@
+///////////////////////////////////////////////////////////////////////////////
+// NAME:            cors.rs
+//
+// AUTHOR:          Ethan D. Twardy <ethan.twardy@gmail.com>
+//
+// DESCRIPTION:     Cross-origin resource sharing headers.
+//
+// CREATED:         10/15/2026
+//
+// LAST EDITED:     10/15/2026
+////
+
+use hyper::{
+    Body, Method, Request, Response, StatusCode,
+    header::HeaderValue,
+};
+
+// Any method may be used in development.
+const ALLOWED_METHODS: &str = "GET, HEAD, POST, PUT, PATCH, DELETE, OPTIONS";
+
+// How long browsers may cache the result of a preflight request, in seconds.
+// Kept short, so that changes to the configuration are picked up quickly.
+const MAX_AGE: &str = "60";
+
+pub struct Cors {
+    // Origins allowed to make requests, like "http://localhost:3000". An
+    // origin of "*" allows any.
+    origins: Vec<String>,
+    // Allow requests with credentials, like cookies.
+    credentials: bool,
+}
+
+impl Cors {
+    pub fn new(origins: Vec<String>, credentials: bool) -> Self {
+        Self { origins, credentials }
+    }
+
+    fn allows(&self, origin: &HeaderValue) -> bool {
+        origin.to_str().is_ok_and(|origin| {
+            self.origins.iter()
+                .any(|allowed| allowed == "*" || allowed == origin)
+        })
+    }
+
+    // Answer a preflight request. Any other request gets None. The origin is
+    // allowed (or not) by `apply`, as for any other response.
+    pub fn preflight(&self, request: &Request<Body>) ->
+        Option<Response<Body>>
+    {
+        let headers = request.headers();
+        if request.method() != Method::OPTIONS
+            || !headers.contains_key("access-control-request-method")
+        {
+            return None;
+        }
+
+        let mut response = Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(Body::empty())
+            .unwrap();
+        if !headers.get("origin").is_some_and(|origin| self.allows(origin)) {
+            return Some(response);
+        }
+
+        let response_headers = response.headers_mut();
+        response_headers.insert("access-control-allow-methods",
+                                HeaderValue::from_static(ALLOWED_METHODS));
+        let requested = headers.get("access-control-request-headers");
+        if let Some(requested) = requested {
+            response_headers.insert(
+                "access-control-allow-headers", requested.clone());
+        }
+        response_headers.insert("access-control-max-age",
+                                HeaderValue::from_static(MAX_AGE));
+        Some(response)
+    }
+
+    // Add the headers for a response to a request from `origin`. Responses
+    // to requests from disallowed origins get none.
+    pub fn apply(&self, origin: Option<HeaderValue>,
+                 response: &mut Response<Body>)
+    {
+        let headers = response.headers_mut();
+        headers.append("vary", HeaderValue::from_static("origin"));
+        let origin = match origin.filter(|origin| self.allows(origin)) {
+            Some(origin) => origin,
+            None => return,
+        };
+
+        headers.insert("access-control-allow-origin", origin);
+        if self.credentials {
+            headers.insert("access-control-allow-credentials",
+                           HeaderValue::from_static("true"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: Method, headers: &[(&str, &str)]) -> Request<Body> {
+        let mut request = Request::builder().method(method).uri("/api");
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        request.body(Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn only_preflight_requests_are_answered() {
+        let cors = Cors::new(vec!["*".to_string()], false);
+        assert!(cors.preflight(&request(Method::OPTIONS, &[])).is_none());
+        assert!(cors.preflight(&request(
+            Method::GET, &[("access-control-request-method", "PUT")]))
+                .is_none());
+    }
+
+    #[test]
+    fn disallowed_origins_get_no_headers() {
+        let cors = Cors::new(vec!["http://localhost:3000".to_string()], true);
+        let response = cors.preflight(&request(Method::OPTIONS, &[
+            ("origin", "http://evil.test"),
+            ("access-control-request-method", "PUT"),
+        ])).unwrap();
+        assert!(!response.headers()
+                .contains_key("access-control-allow-methods"));
+
+        let mut response = Response::new(Body::empty());
+        cors.apply(Some(HeaderValue::from_static("http://evil.test")),
+                   &mut response);
+        assert!(!response.headers()
+                .contains_key("access-control-allow-origin"));
+        assert_eq!(response.headers()["vary"], "origin");
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
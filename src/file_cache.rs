@@ -0,0 +1,281 @@
+///////////////////////////////////////////////////////////////////////////////
+// NAME:            file_cache.rs
+//
+// AUTHOR:          Ethan D. Twardy <ethan.twardy@gmail.com>
+//
+// DESCRIPTION:     In-memory cache of responses for small static files.
+//
+// CREATED:         10/14/2026
+//
+// LAST EDITED:     10/15/2026
+////
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use hyper::{
+    body::Bytes,
+    header::{HeaderMap, HeaderValue},
+    http::request,
+};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+// What a cached response depends on, besides the files themselves: the roots
+// it was served from, the path it was requested at, and the headers that
+// choose between the variants of a file.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Key {
+    roots: Vec<PathBuf>,
+    path: String,
+    accept: Option<HeaderValue>,
+    accept_encoding: Option<HeaderValue>,
+}
+
+impl Key {
+    pub fn new(roots: &[PathBuf], request: &request::Parts) -> Self {
+        Self {
+            roots: roots.to_vec(),
+            path: request.uri.path().to_string(),
+            accept: request.headers.get("accept").cloned(),
+            accept_encoding: request.headers.get("accept-encoding").cloned(),
+        }
+    }
+}
+
+// The file that a response was read from, and when it was last modified.
+#[derive(Clone)]
+pub struct Source {
+    pub path: PathBuf,
+    pub modified: SystemTime,
+}
+
+impl Source {
+    // Whether the file hasn't been modified since the response was read.
+    pub async fn is_current(&self) -> bool {
+        tokio::fs::metadata(&self.path).await
+            .and_then(|metadata| metadata.modified())
+            .is_ok_and(|modified| modified == self.modified)
+    }
+}
+
+struct Entry {
+    headers: HeaderMap,
+    contents: Bytes,
+    source: Source,
+    // When the entry was last used, for evicting the least recently used.
+    used: u64,
+}
+
+#[derive(Default)]
+struct Entries {
+    entries: HashMap<Key, Entry>,
+    // The total size of the contents of the entries.
+    size: u64,
+    clock: u64,
+    // Counts the times the entries were dropped, so that a response read
+    // from the files before they changed isn't cached after they were.
+    generation: u64,
+}
+
+impl Entries {
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.size = 0;
+        self.generation += 1;
+    }
+}
+
+// Whole responses, holding no more than `limit` bytes of contents in total.
+// Entries are dropped by a watcher whenever anything under the roots changes.
+// Watchers can miss changes, like those made on network filesystems, so the
+// file a hit was read from is checked too, which is cheaper than reading it.
+pub struct FileCache {
+    limit: u64,
+    entries: Arc<Mutex<Entries>>,
+    // Watching stops when the watcher is dropped.
+    _watcher: Mutex<RecommendedWatcher>,
+}
+
+impl FileCache {
+    // Cache files from `roots`, dropping every entry whenever anything in
+    // them (or their subdirectories) changes. A new file can change which
+    // one a path resolves to, or add a precompressed copy of it, so it isn't
+    // enough to drop the entries of the file that changed.
+    pub fn watch(limit: u64, roots: &[PathBuf]) -> notify::Result<Self> {
+        let entries = Arc::new(Mutex::new(Entries::default()));
+        let watched = entries.clone();
+        let mut watcher = notify::recommended_watcher(
+            move |event: notify::Result<Event>| match event {
+                Ok(event) if matches!(event.kind, EventKind::Access(_)) => {},
+                Ok(_) => watched.lock().unwrap().clear(),
+                Err(error) => {
+                    log::warn!("error watching for changes: {}", error);
+                    watched.lock().unwrap().clear();
+                },
+            })?;
+        for root in roots {
+            watcher.watch(&fs::canonicalize(root)?, RecursiveMode::Recursive)?;
+        }
+
+        Ok(Self { limit, entries, _watcher: Mutex::new(watcher) })
+    }
+
+    // Whether a response of `size` bytes may be cached.
+    pub fn admits(&self, size: u64) -> bool {
+        size <= self.limit
+    }
+
+    // The generation of the entries, to be passed to `insert` with a
+    // response that's read after this is called.
+    pub fn generation(&self) -> u64 {
+        self.entries.lock().unwrap().generation
+    }
+
+    // Get the headers and contents of the response for `key`, if it's cached,
+    // and the file that it was read from.
+    pub fn get(&self, key: &Key) -> Option<(HeaderMap, Bytes, Source)> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock += 1;
+        let clock = entries.clock;
+        let entry = entries.entries.get_mut(key)?;
+        entry.used = clock;
+        Some((entry.headers.clone(), entry.contents.clone(),
+              entry.source.clone()))
+    }
+
+    // Cache a response, unless the entries were dropped since `generation`,
+    // in which case it may have been read from files that have since
+    // changed.
+    pub fn insert(&self, key: Key, generation: u64, headers: HeaderMap,
+                  contents: Bytes, source: Source)
+    {
+        let size = contents.len() as u64;
+        if !self.admits(size) {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries.generation != generation {
+            return;
+        }
+
+        if let Some(previous) = entries.entries.remove(&key) {
+            entries.size -= previous.contents.len() as u64;
+        }
+
+        while entries.size + size > self.limit {
+            let oldest = entries.entries.iter()
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(key, _)| key.clone())
+                .unwrap();
+            let evicted = entries.entries.remove(&oldest).unwrap();
+            entries.size -= evicted.contents.len() as u64;
+        }
+
+        entries.clock += 1;
+        let used = entries.clock;
+        entries.size += size;
+        entries.entries.insert(
+            key, Entry { headers, contents, source, used });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(path: &str) -> Key {
+        let (request, _) = hyper::Request::get(path).body(()).unwrap()
+            .into_parts();
+        Key::new(&[PathBuf::from("/srv")], &request)
+    }
+
+    // A cache holding `limit` bytes, watching a directory that lives as long
+    // as it does.
+    fn cache(limit: u64) -> (tempfile::TempDir, FileCache) {
+        let root = tempfile::tempdir().unwrap();
+        let cache = FileCache::watch(limit, &[root.path().to_path_buf()])
+            .unwrap();
+        (root, cache)
+    }
+
+    // Cache `contents` at `path`, as read from a file that never changes.
+    fn insert(cache: &FileCache, path: &str, contents: &'static str) {
+        let source = Source {
+            path: PathBuf::from(format!("/srv{}", path)),
+            modified: SystemTime::UNIX_EPOCH,
+        };
+        cache.insert(key(path), cache.generation(), HeaderMap::new(),
+                     Bytes::from(contents), source);
+    }
+
+    #[test]
+    fn the_least_recently_used_entry_is_evicted() {
+        let (_root, cache) = cache(8);
+        insert(&cache, "/a", "aaaa");
+        insert(&cache, "/b", "bbbb");
+        assert!(cache.get(&key("/a")).is_some());
+
+        insert(&cache, "/c", "cccc");
+        assert!(cache.get(&key("/a")).is_some());
+        assert!(cache.get(&key("/b")).is_none());
+        assert!(cache.get(&key("/c")).is_some());
+    }
+
+    #[test]
+    fn responses_larger_than_the_limit_are_not_cached() {
+        let (_root, cache) = cache(2);
+        insert(&cache, "/a", "aaa");
+        assert!(cache.get(&key("/a")).is_none());
+    }
+
+    #[test]
+    fn variants_are_cached_separately() {
+        let (_root, cache) = cache(64);
+        let (gzip, _) = hyper::Request::get("/a")
+            .header("accept-encoding", "gzip")
+            .body(()).unwrap()
+            .into_parts();
+        insert(&cache, "/a", "plain");
+        assert!(cache.get(&Key::new(&[PathBuf::from("/srv")], &gzip))
+                .is_none());
+    }
+
+    #[test]
+    fn responses_read_before_the_entries_were_dropped_are_not_cached() {
+        let (_root, cache) = cache(64);
+        let generation = cache.generation();
+        cache.entries.lock().unwrap().clear();
+        let source = Source {
+            path: PathBuf::from("/srv/a"), modified: SystemTime::UNIX_EPOCH,
+        };
+        cache.insert(key("/a"), generation, HeaderMap::new(),
+                     Bytes::from("stale"), source);
+        assert!(cache.get(&key("/a")).is_none());
+
+        insert(&cache, "/a", "fresh");
+        assert_eq!(cache.get(&key("/a")).unwrap().1, "fresh");
+    }
+
+    #[tokio::test]
+    async fn sources_are_current_until_their_file_is_modified() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().join("a");
+        std::fs::write(&path, "a").unwrap();
+        let modified = std::fs::metadata(&path).unwrap().modified().unwrap();
+        let source = Source { path: path.clone(), modified };
+        assert!(source.is_current().await);
+
+        let stale = Source {
+            path: path.clone(), modified: SystemTime::UNIX_EPOCH,
+        };
+        assert!(!stale.is_current().await);
+        std::fs::remove_file(&path).unwrap();
+        assert!(!source.is_current().await);
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
@@ -0,0 +1,190 @@
+///////////////////////////////////////////////////////////////////////////////
+// NAME:            inject.rs
+//
+// AUTHOR:          Ethan D. Twardy <ethan.twardy@gmail.com>
+//
+// DESCRIPTION:     Injection of snippets into HTML documents.
+//
+// CREATED:         10/15/2026
+//
+// LAST EDITED:     10/15/2026
+////
+
+use hyper::{
+    Body, Response, StatusCode,
+    header::{self, HeaderValue},
+};
+
+use crate::compression::{self, Encoding};
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    needle.is_empty()
+        || haystack.windows(needle.len()).any(|window| window == needle)
+}
+
+// Insert `snippets` before the closing body tag of an HTML document, or at the
+// end of it if there isn't one. A snippet that the document already contains
+// isn't inserted again.
+pub fn inject(html: &[u8], snippets: &[String]) -> Vec<u8> {
+    let position = html.windows(b"</body>".len())
+        .rposition(|window| window.eq_ignore_ascii_case(b"</body>"))
+        .unwrap_or(html.len());
+    let mut injected = Vec::with_capacity(
+        html.len() + snippets.iter().map(String::len).sum::<usize>());
+    injected.extend_from_slice(&html[..position]);
+    for snippet in snippets {
+        if !contains(html, snippet.as_bytes()) {
+            injected.extend_from_slice(snippet.as_bytes());
+        }
+    }
+
+    injected.extend_from_slice(&html[position..]);
+    injected
+}
+
+// A weak version of an entity tag. The document it tags is still the same,
+// but its bytes aren't.
+fn weaken(etag: &HeaderValue) -> Option<HeaderValue> {
+    if etag.as_bytes().starts_with(b"W/") {
+        return Some(etag.clone());
+    }
+
+    let mut weak = b"W/".to_vec();
+    weak.extend_from_slice(etag.as_bytes());
+    HeaderValue::from_bytes(&weak).ok()
+}
+
+// Inject `snippets` into a proxied HTML document. Documents compressed by the
+// upstream are decompressed first. Any other response, or a document in an
+// encoding that can't be decoded, is left alone. So is a document of more
+// than `limit` bytes, except that it's still decompressed.
+pub async fn inject_response(response: Response<Body>, snippets: &[String],
+                             limit: u64) ->
+    Result<Response<Body>, hyper::Error>
+{
+    let headers = response.headers();
+    let html = response.status() == StatusCode::OK
+        && headers.get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.trim_start().starts_with("text/html"));
+    if !html {
+        return Ok(response);
+    }
+
+    let encoding = match headers.get(header::CONTENT_ENCODING) {
+        Some(value) => match value.to_str().ok()
+            .and_then(Encoding::from_name)
+        {
+            Some(encoding) => Some(encoding),
+            None => return Ok(response),
+        },
+        None => None,
+    };
+
+    // The upstream's validators describe the bytes that it sent.
+    let (mut parts, body) = response.into_parts();
+    let etag = parts.headers.remove(header::ETAG)
+        .and_then(|etag| weaken(&etag));
+    if let Some(etag) = etag {
+        parts.headers.insert(header::ETAG, etag);
+    }
+    parts.headers.remove("content-md5");
+    parts.headers.remove(header::LAST_MODIFIED);
+
+    let body = match encoding {
+        Some(encoding) => {
+            parts.headers.remove(header::CONTENT_ENCODING);
+            parts.headers.remove(header::CONTENT_LENGTH);
+            compression::decompress(body, encoding)
+        },
+        None => body,
+    };
+
+    let html = match crate::buffer(body, limit).await? {
+        Ok(html) => inject(&html, snippets),
+        Err(body) => return Ok(Response::from_parts(parts, body)),
+    };
+    parts.headers.insert(
+        header::CONTENT_LENGTH, HeaderValue::from(html.len()));
+    Ok(Response::from_parts(parts, Body::from(html)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snippets() -> Vec<String> {
+        vec!["<script src=\"/dev.js\"></script>".to_string()]
+    }
+
+    #[test]
+    fn snippets_go_before_the_closing_body_tag() {
+        let injected = inject(b"<body><p>hi</p></BODY></html>", &snippets());
+        assert_eq!(injected, b"<body><p>hi</p><script src=\"/dev.js\">\
+                               </script></BODY></html>");
+
+        // Without a body tag, at the end; and never twice.
+        assert_eq!(inject(b"<p>hi</p>", &snippets()),
+                   b"<p>hi</p><script src=\"/dev.js\"></script>");
+        assert_eq!(inject(&injected, &snippets()), injected);
+    }
+
+    #[tokio::test]
+    async fn compressed_documents_are_injected_into() {
+        let html = "<body>hi</body>";
+        let response = Response::builder()
+            .header("content-type", "text/html; charset=utf-8")
+            .header("content-encoding", "gzip")
+            .body(compression::compress(Body::from(html), Encoding::Gzip,
+                                        compression::DEFAULT_LEVEL))
+            .unwrap();
+        let response = inject_response(response, &snippets(), 1024).await
+            .unwrap();
+        assert!(response.headers().get("content-encoding").is_none());
+        let expected = "<body>hi<script src=\"/dev.js\"></script></body>";
+        assert_eq!(response.headers()["content-length"],
+                   expected.len().to_string());
+        assert_eq!(hyper::body::to_bytes(response.into_body()).await.unwrap(),
+                   expected);
+    }
+
+    #[tokio::test]
+    async fn the_upstreams_validators_are_weakened_or_dropped() {
+        let response = Response::builder()
+            .header("content-type", "text/html")
+            .header("etag", "\"abc\"")
+            .header("content-md5", "Q2hlY2sgSW50ZWdyaXR5IQ==")
+            .header("last-modified", "Wed, 14 Oct 2026 00:00:00 GMT")
+            .body(Body::from("<body>hi</body>")).unwrap();
+        let response = inject_response(response, &snippets(), 1024).await
+            .unwrap();
+        assert_eq!(response.headers()["etag"], "W/\"abc\"");
+        assert!(!response.headers().contains_key("content-md5"));
+        assert!(!response.headers().contains_key("last-modified"));
+    }
+
+    #[tokio::test]
+    async fn documents_over_the_limit_are_left_alone() {
+        let html = "<body>a long document</body>";
+        let response = Response::builder()
+            .header("content-type", "text/html")
+            .body(Body::from(html)).unwrap();
+        let response = inject_response(response, &snippets(), 8).await
+            .unwrap();
+        assert_eq!(hyper::body::to_bytes(response.into_body()).await.unwrap(),
+                   html);
+    }
+
+    #[tokio::test]
+    async fn other_responses_are_left_alone() {
+        let response = Response::builder()
+            .header("content-type", "application/json")
+            .body(Body::from("{}")).unwrap();
+        let response = inject_response(response, &snippets(), 1024).await
+            .unwrap();
+        assert_eq!(hyper::body::to_bytes(response.into_body()).await.unwrap(),
+                   "{}");
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
@@ -0,0 +1,166 @@
+///////////////////////////////////////////////////////////////////////////////
+// NAME:            listing.rs
+//
+// AUTHOR:          Ethan D. Twardy <ethan.twardy@gmail.com>
+//
+// DESCRIPTION:     Renders HTML listings of directories.
+//
+// CREATED:         10/14/2026
+//
+// LAST EDITED:     10/14/2026
+////
+
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+use tokio::fs;
+
+use crate::percent_encode;
+
+struct Entry {
+    name: String,
+    directory: bool,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+// Escape the characters that are significant in HTML text and attributes.
+fn html_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(character),
+        }
+    }
+
+    escaped
+}
+
+async fn read_entries(directory: &Path, show_hidden: bool) ->
+    io::Result<Vec<Entry>>
+{
+    let mut entries = Vec::new();
+    let mut reader = fs::read_dir(directory).await?;
+    while let Some(entry) = reader.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !show_hidden && name.starts_with('.') {
+            continue;
+        }
+
+        // Follow symlinks, so that links to directories are listed as such.
+        // Broken links are skipped.
+        let metadata = match fs::metadata(entry.path()).await {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+
+        entries.push(Entry {
+            name,
+            directory: metadata.is_dir(),
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+        });
+    }
+
+    // Directories first, then files, each sorted by name.
+    entries.sort_by(|a, b| b.directory.cmp(&a.directory)
+                    .then_with(|| a.name.cmp(&b.name)));
+    Ok(entries)
+}
+
+// Render a listing of `directory`, which was requested at the URL `path`.
+pub async fn render(directory: &Path, path: &str, show_hidden: bool) ->
+    io::Result<String>
+{
+    let entries = read_entries(directory, show_hidden).await?;
+
+    // Links are relative to the requested URL. If it doesn't end with a
+    // slash, the browser resolves them against the parent directory, so the
+    // name of this directory has to be included.
+    let base = match path.rsplit_once('/') {
+        Some((_, "")) | None => String::new(),
+        Some((_, last)) => percent_encode(last) + "/",
+    };
+
+    let title = html_escape(&format!("Index of {}", path));
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{0}</title>\n</head>\n<body>\n<h1>{0}</h1>\n<table>\n\
+         <tr><th>Name</th><th>Size</th><th>Modified</th></tr>\n",
+        title);
+    if path != "/" {
+        html += &format!(
+            "<tr><td><a href=\"{}../\">../</a></td><td></td><td></td></tr>\n",
+            base);
+    }
+
+    for entry in entries {
+        let suffix = if entry.directory { "/" } else { "" };
+        let size = match entry.directory {
+            true => "-".to_string(),
+            false => entry.size.to_string(),
+        };
+        let modified = entry.modified.map(httpdate::fmt_http_date)
+            .unwrap_or_default();
+        html += &format!(
+            "<tr><td><a href=\"{}{}{}\">{}{}</a></td><td>{}</td><td>{}</td>\
+             </tr>\n",
+            base, html_escape(&percent_encode(&entry.name)), suffix,
+            html_escape(&entry.name), suffix, size, modified);
+    }
+
+    html += "</table>\n</body>\n</html>\n";
+    Ok(html)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn special_characters_are_escaped() {
+        assert_eq!(html_escape("<a href=\"x\">Tom & Jerry's</a>"),
+                   "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s\
+                    &lt;/a&gt;");
+    }
+
+    #[tokio::test]
+    async fn directories_are_listed_first() {
+        let root = tempfile::tempdir().unwrap();
+        for name in ["b.txt", "a.txt", ".env"] {
+            std::fs::write(root.path().join(name), "").unwrap();
+        }
+        std::fs::create_dir(root.path().join("z")).unwrap();
+
+        let html = render(root.path(), "/", false).await.unwrap();
+        let z = html.find("href=\"z/\"").unwrap();
+        let a = html.find("href=\"a.txt\"").unwrap();
+        let b = html.find("href=\"b.txt\"").unwrap();
+        assert!(z < a && a < b, "{}", html);
+        assert!(!html.contains(".env"));
+        assert!(!html.contains("../"));
+
+        let html = render(root.path(), "/", true).await.unwrap();
+        assert!(html.contains("href=\".env\""));
+    }
+
+    #[tokio::test]
+    async fn names_are_encoded_in_links_and_escaped_in_text() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("<b> & c.txt"), "").unwrap();
+
+        let html = render(root.path(), "/files", false).await.unwrap();
+        assert!(html.contains("href=\"files/%3Cb%3E%20%26%20c.txt\""),
+                "{}", html);
+        assert!(html.contains(">&lt;b&gt; &amp; c.txt<"), "{}", html);
+        assert!(html.contains("href=\"files/../\""));
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
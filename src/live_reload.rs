@@ -0,0 +1,260 @@
+///////////////////////////////////////////////////////////////////////////////
+// NAME:            live_reload.rs
+//
+// AUTHOR:          Ethan D. Twardy <ethan.twardy@gmail.com>
+//
+// DESCRIPTION:     Reloads pages in the browser when static files change.
+//
+// CREATED:         10/15/2026
+//
+// LAST EDITED:     10/15/2026
+////
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use hyper::{Body, Request, Response, StatusCode};
+use notify::{
+    Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+};
+use tokio::sync::{broadcast, mpsc};
+use tokio::time;
+use tokio_tungstenite::{
+    WebSocketStream,
+    tungstenite::{handshake::derive_accept_key, protocol::Role, Message},
+};
+
+use crate::{empty_response, upgrade_protocol};
+
+// The WebSocket endpoint that pages listen on for changes.
+pub const SOCKET_PATH: &str = "/__dev_proxy_livereload";
+
+// Changes that come closer together than this are reported as one, so that a
+// build writing out many files only causes a single reload.
+const DEBOUNCE: Duration = Duration::from_millis(150);
+
+// Injected into HTML documents. Stylesheets are swapped out in place when only
+// they have changed, so that the state of the page is kept.
+pub const SCRIPT: &str = r#"<script>
+(function () {
+  var scheme = window.location.protocol === "https:" ? "wss://" : "ws://";
+  var socket = new WebSocket(
+    scheme + window.location.host + "/__dev_proxy_livereload");
+  socket.addEventListener("message", function (event) {
+    if (event.data !== "css") {
+      window.location.reload();
+      return;
+    }
+
+    var links = document.querySelectorAll('link[rel="stylesheet"]');
+    Array.prototype.forEach.call(links, function (link) {
+      var url = new URL(link.href);
+      url.searchParams.set("__dev_proxy__", Date.now());
+      link.href = url.href;
+    });
+  });
+})();
+</script>
+"#;
+
+#[derive(Clone, Copy)]
+enum Change {
+    Reload,
+    Stylesheet,
+}
+
+impl Change {
+    fn message(&self) -> Message {
+        match self {
+            Self::Reload => Message::Text("reload".to_string()),
+            Self::Stylesheet => Message::Text("css".to_string()),
+        }
+    }
+}
+
+// Send a message to the page on `socket` for each change, until it goes
+// away. Whatever the page sends is read only to notice that.
+async fn notify_page<S>(mut socket: WebSocketStream<S>,
+                        mut changes: broadcast::Receiver<Change>)
+where S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    loop {
+        let change = tokio::select! {
+            change = changes.recv() => change,
+            message = socket.next() => match message {
+                Some(Ok(_)) => continue,
+                _ => return,
+            },
+        };
+        let change = match change {
+            Ok(change) => change,
+            // Whatever was missed, the page is out of date.
+            Err(broadcast::error::RecvError::Lagged(_)) => Change::Reload,
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        if socket.send(change.message()).await.is_err() {
+            return;
+        }
+    }
+}
+
+fn is_stylesheet(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("css"))
+}
+
+// Editors write swap and backup files next to the ones being edited, and
+// version control keeps its state in hidden directories. Neither is served,
+// so changes to them are ignored. Only the path under the root is checked.
+fn is_hidden(roots: &[PathBuf], path: &Path) -> bool {
+    let relative = roots.iter()
+        .find_map(|root| path.strip_prefix(root).ok())
+        .unwrap_or(path);
+    relative.components().any(|component| match component {
+        Component::Normal(name) => name.to_string_lossy().starts_with('.'),
+        _ => false,
+    })
+}
+
+// Collect bursts of changed files into a single change, which is a reload
+// unless every file in the burst was a stylesheet.
+async fn debounce(mut paths: mpsc::UnboundedReceiver<PathBuf>,
+                  changes: broadcast::Sender<Change>)
+{
+    while let Some(path) = paths.recv().await {
+        let mut stylesheets = is_stylesheet(&path);
+        loop {
+            match time::timeout(DEBOUNCE, paths.recv()).await {
+                Ok(Some(path)) => stylesheets &= is_stylesheet(&path),
+                Ok(None) => return,
+                Err(_) => break,
+            }
+        }
+
+        let change = match stylesheets {
+            true => Change::Stylesheet,
+            false => Change::Reload,
+        };
+        // There may be no pages listening, which is fine.
+        let _ = changes.send(change);
+    }
+}
+
+pub struct LiveReload {
+    changes: broadcast::Sender<Change>,
+    // Watching stops when the watcher is dropped.
+    _watcher: Mutex<RecommendedWatcher>,
+}
+
+impl LiveReload {
+    // Watch the directories in `roots` (and their subdirectories) for
+    // changes. This has to be called from within the runtime.
+    pub fn watch(roots: &[PathBuf]) -> notify::Result<Self> {
+        // Changed paths are reported under the path that's watched.
+        let roots = roots.iter()
+            .map(fs::canonicalize)
+            .collect::<Result<Vec<PathBuf>, _>>()?;
+        let watched = roots.clone();
+        let (paths, receiver) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(
+            move |event: notify::Result<Event>| {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(error) => {
+                        log::warn!("error watching for changes: {}", error);
+                        return;
+                    },
+                };
+
+                if matches!(event.kind, EventKind::Access(_)) {
+                    return;
+                }
+
+                for path in event.paths {
+                    if !is_hidden(&watched, &path) {
+                        let _ = paths.send(path);
+                    }
+                }
+            })?;
+        for root in &roots {
+            watcher.watch(root, RecursiveMode::Recursive)?;
+        }
+
+        let (changes, _) = broadcast::channel(16);
+        tokio::spawn(debounce(receiver, changes.clone()));
+        Ok(Self { changes, _watcher: Mutex::new(watcher) })
+    }
+
+    // Accept a WebSocket connection from a page, which is sent a message
+    // for each change: "css" if only stylesheets changed, or "reload".
+    pub fn connect(&self, mut request: Request<Body>) -> Response<Body> {
+        let websocket = upgrade_protocol(request.headers())
+            .is_some_and(|protocol| protocol.as_bytes()
+                         .eq_ignore_ascii_case(b"websocket"));
+        let key = match request.headers().get("sec-websocket-key") {
+            Some(key) if websocket => key.clone(),
+            _ => return empty_response(StatusCode::UPGRADE_REQUIRED),
+        };
+
+        let changes = self.changes.subscribe();
+        let upgrade = hyper::upgrade::on(&mut request);
+        tokio::spawn(async move {
+            match upgrade.await {
+                Ok(upgraded) => notify_page(WebSocketStream::from_raw_socket(
+                    upgraded, Role::Server, None).await, changes).await,
+                Err(error) => log::warn!(
+                    "live reload connection failed: {}", error),
+            }
+        });
+
+        Response::builder()
+            .status(StatusCode::SWITCHING_PROTOCOLS)
+            .header("connection", "upgrade")
+            .header("upgrade", "websocket")
+            .header("sec-websocket-accept", derive_accept_key(key.as_bytes()))
+            .body(Body::empty())
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_to_hidden_files_are_ignored() {
+        let roots = [PathBuf::from("/srv/.site")];
+        assert!(is_hidden(&roots, Path::new("/srv/.site/.index.html.swp")));
+        assert!(is_hidden(&roots, Path::new("/srv/.site/.git/index")));
+        assert!(!is_hidden(&roots, Path::new("/srv/.site/index.html")));
+    }
+
+    #[tokio::test]
+    async fn a_burst_of_stylesheets_is_one_css_change() {
+        let (paths, receiver) = mpsc::unbounded_channel();
+        let (changes, mut listener) = broadcast::channel(16);
+        tokio::spawn(debounce(receiver, changes));
+        paths.send(PathBuf::from("a.css")).unwrap();
+        paths.send(PathBuf::from("b.CSS")).unwrap();
+        assert!(matches!(listener.recv().await, Ok(Change::Stylesheet)));
+
+        paths.send(PathBuf::from("a.css")).unwrap();
+        paths.send(PathBuf::from("index.html")).unwrap();
+        assert!(matches!(listener.recv().await, Ok(Change::Reload)));
+    }
+
+    #[tokio::test]
+    async fn plain_requests_for_the_socket_are_refused() {
+        let root = tempfile::tempdir().unwrap();
+        let live_reload = LiveReload::watch(&[root.path().to_path_buf()])
+            .unwrap();
+        let request = Request::get(SOCKET_PATH).body(Body::empty()).unwrap();
+        assert_eq!(live_reload.connect(request).status(),
+                   StatusCode::UPGRADE_REQUIRED);
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
@@ -10,26 +10,84 @@
 // LAST EDITED:     04/18/2022
 ////
 
+// The section banners in this crate are not doc comments.
+#![allow(clippy::four_forward_slashes)]
+
+mod auth;
+mod cache_control;
+mod cli;
+mod compression;
+mod config;
+mod cors;
+mod file_cache;
+mod inject;
+mod listing;
+mod live_reload;
+mod metrics;
+mod reload;
+mod substitute;
+mod tls;
+#[cfg(unix)]
+mod unix;
+mod write;
+
 use core::convert::Infallible;
 use core::task::{Context, Poll};
-use core::future::Future;
+use core::future::{ready, Future};
 use core::pin::Pin;
 
-use std::env::current_dir;
+use std::collections::HashMap;
+use std::fs::Metadata;
 use std::error::Error;
-use std::fs::File;
-use std::io::{self, Read};
-use std::path::PathBuf;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Component, Path, PathBuf};
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::fmt;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use futures_util::{stream, StreamExt};
 use hyper::{
     Body, Client,
-    client::{connect::HttpConnector, ResponseFuture},
-    Request, Response,
+    body::{Bytes, HttpBody},
+    header::{HeaderMap, HeaderName, HeaderValue},
+    http::{request, response, uri::PathAndQuery},
+    client::connect::HttpConnector,
+    upgrade::OnUpgrade,
+    Method, Request, Response, StatusCode,
     server::conn::AddrStream,
-    service::{make_service_fn, Service},
+    service::make_service_fn,
     Uri,
 };
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use regex::Regex;
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncSeekExt},
+    time::{self, error::Elapsed},
+};
+use tokio_rustls::rustls::ClientConfig;
+use tokio_util::io::ReaderStream;
+
+use clap::Parser;
+
+use auth::BasicAuth;
+use cache_control::{cache_control, CacheRule};
+use cli::Args;
+use compression::{Encoding, Level, MIN_COMPRESS_SIZE};
+use config::{
+    Config, ConfigError, ForwardedStyle, MountConfig, RewriteRule,
+    RouteConfig, SymlinkPolicy, DEFAULT_CONFIG_FILE,
+};
+use cors::Cors;
+use file_cache::FileCache;
+use live_reload::LiveReload;
+use metrics::Metrics;
+use reload::ReloadableService;
+use substitute::Substitutions;
 
 ///////////////////////////////////////////////////////////////////////////////
 // ProxyError
@@ -39,6 +97,7 @@ use hyper::{
 pub enum ProxyError {
     Proxy(io::Error),
     Http(hyper::Error),
+    Request(hyper::http::Error),
 }
 
 impl fmt::Display for ProxyError {
@@ -46,6 +105,7 @@ impl fmt::Display for ProxyError {
         match &self {
             Self::Proxy(error) => write!(f, "{}", error),
             Self::Http(error) => write!(f, "{}", error),
+            Self::Request(error) => write!(f, "{}", error),
         }
     }
 }
@@ -62,6 +122,12 @@ impl From<hyper::Error> for ProxyError {
     }
 }
 
+impl From<hyper::http::Error> for ProxyError {
+    fn from(error: hyper::http::Error) -> Self {
+        Self::Request(error)
+    }
+}
+
 impl Error for ProxyError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         None
@@ -72,25 +138,97 @@ impl Error for ProxyError {
 // ProxyResponseFuture
 ////
 
-struct ProxyResponseFuture(ResponseFuture);
+// Failing to reach the upstream isn't an error in the proxy, so it's reported
+// to the client as a 502, or a 504 if it doesn't respond in time. Failing to
+// build the upstream request is, though.
+enum ProxyResponseFuture {
+    Pending {
+        response: UpstreamFuture,
+        rewrite: LocationRewrite,
+        // Headers set on the response, replacing any from the upstream.
+        headers: Arc<HeaderMap>,
+        // The client's side of the connection, if it asked to upgrade it.
+        upgrade: Option<OnUpgrade>,
+    },
+    // Answered without asking the upstream.
+    Responded(Option<Response<Body>>),
+    Failed(Option<ProxyError>),
+}
+
+fn gateway_error(status: StatusCode) -> Response<Body> {
+    let message = format!("{} {}\n", status.as_u16(),
+                          status.canonical_reason().unwrap_or_default());
+    Response::builder()
+        .status(status)
+        .header("content-type", "text/plain")
+        .body(Body::from(message))
+        .unwrap()
+}
+
 impl Future for ProxyResponseFuture {
     type Output = Result<Response<Body>, ProxyError>;
     fn poll(mut self: Pin<&mut Self>, context: &mut Context<'_>) ->
         Poll<Self::Output>
     {
-        match Pin::new(&mut self.0).poll(context) {
+        let (future, rewrite, headers, upgrade) = match &mut *self {
+            Self::Pending { response, rewrite, headers, upgrade } =>
+                (response, rewrite, headers, upgrade),
+            Self::Responded(response) => return Poll::Ready(
+                Ok(response.take().expect("polled after completion"))),
+            Self::Failed(error) => return Poll::Ready(
+                Err(error.take().expect("polled after completion"))),
+        };
+
+        match future.as_mut().poll(context) {
             Poll::Pending => Poll::Pending,
             Poll::Ready(response) => match response {
-                Ok(response) => Poll::Ready(Ok(response)),
-                Err(err) => Poll::Ready(Err(err.into())),
+                Ok(Ok(mut response)) => {
+                    if response.status() == StatusCode::SWITCHING_PROTOCOLS {
+                        if let Some(client) = upgrade.take() {
+                            tunnel(client, hyper::upgrade::on(&mut response));
+                        }
+                    }
+                    let mut response = rewrite.apply(response);
+                    for (name, value) in headers.iter() {
+                        response.headers_mut()
+                            .insert(name.clone(), value.clone());
+                    }
+                    Poll::Ready(Ok(response))
+                },
+                Ok(Err(error)) => {
+                    log::warn!("upstream request failed: {}", error);
+                    Poll::Ready(Ok(gateway_error(StatusCode::BAD_GATEWAY)))
+                },
+                Err(_) => {
+                    log::warn!("upstream request timed out");
+                    Poll::Ready(Ok(gateway_error(StatusCode::GATEWAY_TIMEOUT)))
+                },
             },
         }
     }
 }
 
-impl From<ResponseFuture> for ProxyResponseFuture {
-    fn from(response: ResponseFuture) -> Self {
-        Self(response)
+// Once the upstream has agreed to switch protocols, the client is sent its
+// response, and bytes are copied both ways between the two connections until
+// either closes.
+fn tunnel(client: OnUpgrade, upstream: OnUpgrade) {
+    tokio::spawn(async move {
+        let result = async {
+            let (mut client, mut upstream) =
+                tokio::try_join!(client, upstream)?;
+            tokio::io::copy_bidirectional(&mut client, &mut upstream).await?;
+            Ok::<_, ProxyError>(())
+        };
+
+        if let Err(error) = result.await {
+            log::warn!("upgraded connection failed: {}", error);
+        }
+    });
+}
+
+impl From<ProxyError> for ProxyResponseFuture {
+    fn from(error: ProxyError) -> Self {
+        Self::Failed(Some(error))
     }
 }
 
@@ -98,142 +236,4617 @@ impl From<ResponseFuture> for ProxyResponseFuture {
 // Proxy
 ////
 
+// Upstreams may be reached over either http or https, depending on the scheme
+// of their URI. Certificates are verified against the platform's roots.
+type UpstreamClient = Client<HttpsConnector<HttpConnector>>;
+
+// The response from an upstream, which may be delayed, or the error from the
+// last attempt to get it, if it failed or timed out.
+type UpstreamResult = Result<Result<Response<Body>, hyper::Error>, Elapsed>;
+type UpstreamFuture =
+    Pin<Box<dyn Future<Output = UpstreamResult> + Send + Sync>>;
+
+// A client that verifies upstreams as `tls` says, if given.
+fn upstream_client(tls: Option<ClientConfig>) -> UpstreamClient {
+    let connector = match tls {
+        Some(tls) => HttpsConnectorBuilder::new().with_tls_config(tls),
+        None => HttpsConnectorBuilder::new().with_native_roots(),
+    };
+    Client::builder().build(connector.https_or_http().enable_http1().build())
+}
+
+// Fails a share of requests on purpose, to see how an app copes with an
+// unreliable backend. Clones of a route share its generator, so that a seeded
+// one fails the same requests every time.
+#[derive(Clone)]
+struct FaultInjection {
+    rate: f32,
+    status: StatusCode,
+    rng: Arc<Mutex<StdRng>>,
+}
+
+impl FaultInjection {
+    // Fail `rate` (between 0 and 1) of requests with `status`. Without a
+    // seed, the generator is seeded randomly.
+    pub fn new(rate: f32, status: StatusCode, seed: Option<u64>) -> Self {
+        let rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        Self { rate, status, rng: Arc::new(Mutex::new(rng)) }
+    }
+
+    // The response to fail the next request with, if it's to fail.
+    fn strike(&self) -> Option<Response<Body>> {
+        let roll: f32 = self.rng.lock().unwrap().gen();
+        (roll < self.rate).then(|| gateway_error(self.status))
+    }
+}
+
+// The Host header sent to the upstream.
+#[derive(Clone, Default)]
+enum HostPolicy {
+    // The authority of the upstream URI.
+    #[default]
+    Upstream,
+    // The Host that the client sent.
+    Preserve,
+    Override(HeaderValue),
+}
+
 #[derive(Clone)]
 struct ProxyRoute {
+    // The prefix of the route, or the source of its pattern.
     route: String,
-    proxy: Uri,
-    client: Client<HttpConnector>,
+    // A pattern matched against the whole path, in place of the prefix.
+    pattern: Option<Regex>,
+    // Match only the prefix itself, and if `trailing_slash`, the prefix
+    // followed by a '/'.
+    exact: bool,
+    trailing_slash: bool,
+    // Requests are spread across the upstreams in turn. Clones of the route
+    // share their place in the rotation.
+    upstreams: Vec<Uri>,
+    next: Arc<AtomicUsize>,
+    client: UpstreamClient,
+    // Whether responses from this route may be compressed on the fly.
+    compression: bool,
+    // How long to wait for the upstream to respond.
+    timeout: Duration,
+    // How long to wait before sending each request, to simulate a slow
+    // network.
+    delay: Duration,
+    // How many times a request that could safely be sent again is retried
+    // when the upstream can't be reached, and how long to wait before the
+    // first retry. Each wait is longer than the last.
+    retries: u32,
+    retry_backoff: Duration,
+    // Whether the route's prefix is removed from the path before it's
+    // appended to the upstream URI, and what's put in its place, if anything.
+    strip_prefix: bool,
+    replacement: Option<String>,
+    // Rules rewriting the path, the first that matches of which is used in
+    // place of the above.
+    rewrites: Arc<Vec<RewriteRule>>,
+    // Headers set on requests to the upstream, and on its responses,
+    // replacing any that were already there.
+    add_request_headers: Arc<HeaderMap>,
+    add_response_headers: Arc<HeaderMap>,
+    host: HostPolicy,
+    // The headers saying where requests came from.
+    forwarded: ForwardedStyle,
+    // Requests failed on purpose, if any.
+    faults: Option<FaultInjection>,
 }
 
+// Long enough for slow requests to a backend under development, but short
+// enough that a hung one is noticed.
+const DEFAULT_UPSTREAM_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Long enough for a backend that's restarting to come back up, after a
+// retry or two.
+const DEFAULT_RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
 impl ProxyRoute {
-    pub fn new(route: String, proxy: Uri) -> Self {
-        Self { route, proxy, client: Client::new() }
+    // There must be at least one upstream.
+    pub fn new(route: String, upstreams: Vec<Uri>) -> Self {
+        assert!(!upstreams.is_empty(), "no upstreams for route {}", route);
+        Self {
+            route, pattern: None, exact: false, trailing_slash: false,
+            upstreams,
+            next: Arc::new(AtomicUsize::new(0)),
+            client: upstream_client(None), compression: true,
+            timeout: DEFAULT_UPSTREAM_TIMEOUT, delay: Duration::ZERO,
+            retries: 0, retry_backoff: DEFAULT_RETRY_BACKOFF,
+            strip_prefix: true,
+            replacement: None, rewrites: Arc::new(Vec::new()),
+            add_request_headers: Arc::new(HeaderMap::new()),
+            add_response_headers: Arc::new(HeaderMap::new()),
+            host: HostPolicy::default(),
+            forwarded: ForwardedStyle::default(),
+            faults: None,
+        }
+    }
+
+    // A route for paths that `pattern` matches, rather than a prefix.
+    pub fn with_pattern(pattern: Regex, upstreams: Vec<Uri>) -> Self {
+        let mut route = Self::new(pattern.as_str().to_string(), upstreams);
+        route.pattern = Some(pattern);
+        route
+    }
+
+    // Match only the path that is the prefix, and not those under it.
+    pub fn exact(&mut self, trailing_slash: bool) {
+        self.exact = true;
+        self.trailing_slash = trailing_slash;
+    }
+
+    // Connect to https upstreams with `tls`, rather than verifying them
+    // against the platform's roots.
+    pub fn tls(&mut self, tls: ClientConfig) {
+        self.client = upstream_client(Some(tls));
+    }
+
+    pub fn compression(&mut self, enabled: bool) {
+        self.compression = enabled;
+    }
+
+    pub fn timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub fn delay(&mut self, delay: Duration) {
+        self.delay = delay;
+    }
+
+    pub fn retries(&mut self, retries: u32, backoff: Duration) {
+        self.retries = retries;
+        self.retry_backoff = backoff;
+    }
+
+    pub fn strip_prefix(&mut self, strip: bool) {
+        self.strip_prefix = strip;
+    }
+
+    // Replace the route's prefix with `replacement`, instead of stripping or
+    // keeping it. For a pattern, the part of the path it matched is replaced,
+    // and the replacement may refer to its groups, like "$service".
+    pub fn replace_prefix(&mut self, replacement: String) {
+        self.replacement = Some(replacement);
+    }
+
+    // Rewrite the path and query of requests that `rule` matches, if no
+    // rule added before it does.
+    pub fn rewrite(&mut self, rule: RewriteRule) {
+        Arc::make_mut(&mut self.rewrites).push(rule);
+    }
+
+    pub fn add_request_header(&mut self, name: HeaderName,
+                              value: HeaderValue)
+    {
+        Arc::make_mut(&mut self.add_request_headers).insert(name, value);
+    }
+
+    pub fn add_response_header(&mut self, name: HeaderName,
+                               value: HeaderValue)
+    {
+        Arc::make_mut(&mut self.add_response_headers).insert(name, value);
+    }
+
+    pub fn host(&mut self, policy: HostPolicy) {
+        self.host = policy;
+    }
+
+    pub fn forwarded(&mut self, style: ForwardedStyle) {
+        self.forwarded = style;
+    }
+
+    // Respond to some requests without sending them to the upstream, as if
+    // it had failed. A rate of zero fails none.
+    pub fn inject_faults(&mut self, faults: FaultInjection) {
+        self.faults = Some(faults).filter(|faults| faults.rate > 0.0);
+    }
+
+    pub fn compresses(&self) -> bool {
+        self.compression
     }
 
     pub fn matches(&self, path: &str) -> bool {
-        path.starts_with(&self.route)
+        match &self.pattern {
+            Some(pattern) => pattern.is_match(path),
+            None if self.exact => match path.strip_prefix(&self.route) {
+                Some("") => true,
+                Some("/") => self.trailing_slash,
+                _ => false,
+            },
+            None => prefix_matches(&self.route, path),
+        }
     }
 
-    pub fn request(&self, request: Request<Body>) -> ProxyResponseFuture {
-        let uri: Uri = (
-            self.proxy.to_string()
-                + request.uri().path().strip_prefix(&self.route).unwrap())
-            .parse().unwrap();
-        let proxy_request = Request::builder()
+    pub fn is_pattern(&self) -> bool {
+        self.pattern.is_some()
+    }
+
+    pub fn is_exact(&self) -> bool {
+        self.exact
+    }
+
+    // The path that the route's prefix becomes, under the upstream URI.
+    fn upstream_prefix(&self) -> &str {
+        match (self.replacement.as_deref(), self.strip_prefix) {
+            (Some(replacement), _) => replacement,
+            (None, true) => "",
+            (None, false) => &self.route,
+        }
+    }
+
+    // Choose the upstream for the next request, round-robin.
+    fn next_upstream(&self) -> &Uri {
+        let next = self.next.fetch_add(1, Ordering::Relaxed);
+        &self.upstreams[next % self.upstreams.len()]
+    }
+
+    // Proxy `request`, which was received on `connection`.
+    pub fn request(&self, mut request: Request<Body>,
+                   connection: Connection) -> ProxyResponseFuture
+    {
+        let fault = self.faults.as_ref().and_then(FaultInjection::strike);
+        if let Some(response) = fault {
+            return ProxyResponseFuture::Responded(Some(response));
+        }
+
+        let upgrade = upgrade_protocol(request.headers()).is_some()
+            .then(|| hyper::upgrade::on(&mut request));
+
+        // The URI is only invalid if the configuration of the route made it
+        // so, which is the upstream's fault as far as the client knows.
+        let upstream = self.next_upstream();
+        let uri = match self.upstream_uri(request.uri(), upstream) {
+            Ok(uri) => uri,
+            Err(error) => {
+                log::error!("could not map {} to upstream {}: {}",
+                            request.uri(), upstream, error);
+                return ProxyResponseFuture::Responded(
+                    Some(gateway_error(StatusCode::BAD_GATEWAY)));
+            },
+        };
+
+        match self.upstream_request(request, uri, connection) {
+            Ok(request) => ProxyResponseFuture::Pending {
+                response: self.send(request),
+                rewrite: self.location_rewrite(upstream),
+                headers: self.add_response_headers.clone(),
+                upgrade,
+            },
+            Err(error) => {
+                log::error!("could not build request for upstream {}: {}",
+                          upstream, error);
+                ProxyError::from(error).into()
+            },
+        }
+    }
+
+    // Redirects under a prefix route are mapped back under its prefix. There's
+    // no telling where a pattern came from, so redirects under a pattern
+    // route are only made relative to the proxy.
+    fn location_rewrite(&self, upstream: &Uri) -> LocationRewrite {
+        let (route, prefix) = match self.pattern {
+            Some(_) => ("/".to_string(), String::new()),
+            None => (self.route.clone(), self.upstream_prefix().to_string()),
+        };
+        LocationRewrite { route, upstream: upstream.clone(), prefix }
+    }
+
+    // Send a request to the upstream after the delay, if there is one. The
+    // client doesn't send it until the future is first polled. Requests that
+    // can be retried are sent again if the upstream couldn't be reached, or
+    // closed the connection before it responded. Each attempt has the whole
+    // timeout to itself, and one that times out isn't retried, since the
+    // upstream may have acted on it.
+    fn send(&self, mut request: Request<Body>) -> UpstreamFuture {
+        let retries = match is_retryable(&request) {
+            true => self.retries,
+            false => 0,
+        };
+        let client = self.client.clone();
+        let delay = self.delay;
+        let timeout = self.timeout;
+        let backoff = self.retry_backoff;
+        Box::pin(async move {
+            if !delay.is_zero() {
+                time::sleep(delay).await;
+            }
+
+            let mut attempt = 0;
+            loop {
+                let retry = (attempt < retries)
+                    .then(|| copy_request(&request));
+                let response = time::timeout(timeout, client.request(request));
+                match (response.await, retry) {
+                    // A connection that closes before the request is
+                    // written out cancels it, rather than leaving the
+                    // message incomplete.
+                    (Ok(Err(error)), Some(retry))
+                        if error.is_connect() || error.is_canceled()
+                        || error.is_incomplete_message() =>
+                    {
+                        attempt += 1;
+                        log::warn!("upstream request failed, retrying \
+                                    ({}/{}): {}", attempt, retries, error);
+                        time::sleep(backoff * attempt).await;
+                        request = retry;
+                    },
+                    (result, _) => return result,
+                }
+            }
+        })
+    }
+
+    // The path and query of a request, as rewritten by the first rule that
+    // matches them, if any does.
+    fn rewritten(&self, uri: &Uri) -> Option<String> {
+        let path_and_query = uri.path_and_query()
+            .map_or(uri.path(), |path_and_query| path_and_query.as_str());
+        self.rewrites.iter()
+            .find(|rule| rule.pattern.is_match(path_and_query))
+            .map(|rule| rule.pattern
+                 .replace(path_and_query, rule.replacement.as_str())
+                 .into_owned())
+    }
+
+    // Map the URI of a request onto `upstream`.
+    fn upstream_uri(&self, uri: &Uri, upstream: &Uri) ->
+        Result<Uri, hyper::http::Error>
+    {
+        if let Some(path_and_query) = self.rewritten(uri) {
+            let mut parts = upstream.clone().into_parts();
+            parts.path_and_query = Some(
+                join_path(upstream.path(), &path_and_query).parse()?);
+            return Ok(Uri::from_parts(parts)?);
+        }
+
+        // The query is passed on exactly as it was received, even if empty.
+        let path = match &self.pattern {
+            Some(pattern) => pattern.replace(
+                uri.path(),
+                self.replacement.as_deref().unwrap_or("$0")).into_owned(),
+            None => {
+                let rest = uri.path().strip_prefix(&self.route).unwrap();
+                match self.upstream_prefix() {
+                    "" => rest.to_string(),
+                    prefix => join_path(prefix, rest),
+                }
+            },
+        };
+        let mut path_and_query = join_path(upstream.path(), &path);
+        if let Some(query) = uri.query() {
+            path_and_query.push('?');
+            path_and_query.push_str(query);
+        }
+
+        let mut parts = upstream.clone().into_parts();
+        parts.path_and_query = Some(path_and_query.parse::<PathAndQuery>()?);
+        Ok(Uri::from_parts(parts)?)
+    }
+
+    fn upstream_request(&self, request: Request<Body>, uri: Uri,
+                        connection: Connection) ->
+        Result<Request<Body>, hyper::http::Error>
+    {
+        let mut proxy_request = Request::builder()
             .method(request.method())
-            .uri(uri)
-            .body(request.into_body())
-            .unwrap();
-        self.client.request(proxy_request).into()
+            .uri(uri);
+        // A Forwarded header from the client is replaced with one that
+        // extends it.
+        let style = self.forwarded;
+        let headers = forwarded_headers(request.headers())
+            .filter(|(name, _)| style != ForwardedStyle::Forwarded
+                    || name.as_str() != "forwarded");
+        for (name, value) in headers {
+            proxy_request = proxy_request.header(name, value);
+        }
+
+        match style {
+            ForwardedStyle::XForwarded => {
+                let headers = x_forwarded_headers(&request, connection);
+                for (name, value) in headers {
+                    proxy_request = proxy_request.header(name, value);
+                }
+            },
+            ForwardedStyle::Forwarded => {
+                proxy_request = proxy_request.header(
+                    "forwarded", forwarded_header(&request, connection));
+            },
+            ForwardedStyle::None => {},
+        }
+
+        // The Upgrade header is hop-by-hop, but a request to upgrade the
+        // connection (e.g. to a WebSocket) is passed on to the upstream, so
+        // that the two connections can be joined if it agrees.
+        if let Some(protocol) = upgrade_protocol(request.headers()) {
+            proxy_request = proxy_request
+                .header("connection", "upgrade")
+                .header("upgrade", protocol);
+        }
+
+        // The client's Host is taken before its request is consumed.
+        let host = match &self.host {
+            HostPolicy::Upstream => None,
+            HostPolicy::Preserve => request.headers().get("host").cloned()
+                .or_else(|| request.uri().authority()
+                         .and_then(|host| host.as_str().parse().ok())),
+            HostPolicy::Override(host) => Some(host.clone()),
+        };
+
+        let mut proxy_request = proxy_request.body(request.into_body())?;
+        for (name, value) in self.add_request_headers.iter() {
+            proxy_request.headers_mut().insert(name.clone(), value.clone());
+        }
+
+        // The Host is set last, so that nothing above replaces it. Without
+        // one, the client sends the authority of the upstream URI.
+        match host {
+            Some(host) => proxy_request.headers_mut().insert("host", host),
+            None => proxy_request.headers_mut().remove("host"),
+        };
+
+        Ok(proxy_request)
     }
 }
 
-///////////////////////////////////////////////////////////////////////////////
-// StaticFileFuture
-////
+// Only requests that are safe to send twice, and have no body that would
+// need to be kept for the next attempt, are retried.
+fn is_retryable(request: &Request<Body>) -> bool {
+    matches!(*request.method(), Method::GET | Method::HEAD)
+        && request.body().is_end_stream()
+}
 
-struct StaticFileFuture {
-    path: PathBuf,
+// Copy a request without a body, to send it again.
+fn copy_request(request: &Request<Body>) -> Request<Body> {
+    let mut copy = Request::new(Body::empty());
+    *copy.method_mut() = request.method().clone();
+    *copy.uri_mut() = request.uri().clone();
+    *copy.version_mut() = request.version();
+    *copy.headers_mut() = request.headers().clone();
+    copy
 }
 
-impl StaticFileFuture {
-    pub fn new(path: PathBuf) -> Self {
-        Self { path }
+// Append `rest` to `base` with exactly one '/' between them.
+fn join_path(base: &str, rest: &str) -> String {
+    match (base.ends_with('/'), rest.starts_with('/')) {
+        (true, true) => format!("{}{}", base, &rest[1..]),
+        (false, false) if !rest.is_empty() => format!("{}/{}", base, rest),
+        _ => format!("{}{}", base, rest),
     }
 }
 
-impl Future for StaticFileFuture {
-    type Output = Result<Response<Body>, ProxyError>;
-    fn poll(self: Pin<&mut Self>, _context: &mut Context<'_>) ->
-        Poll<Self::Output>
-    {
-        use io::ErrorKind::*;
+// Whether two URIs have the same scheme, host and port.
+fn same_origin(a: &Uri, b: &Uri) -> bool {
+    let port = |uri: &Uri| uri.port_u16().or(match uri.scheme_str() {
+        Some("http") => Some(80),
+        Some("https") => Some(443),
+        _ => None,
+    });
 
-        let result = File::open(&self.path);
-        let response = match result {
-            Ok(mut file) => {
-                let mut contents = String::new();
-                match file.read_to_string(&mut contents) {
-                    Ok(_) => Ok(Response::builder().status(200)
-                                .body(Body::from(contents)).unwrap()),
-                    Err(error) => Err(error.into()),
-                }
-            },
+    a.scheme() == b.scheme() && port(a) == port(b)
+        && a.host().zip(b.host())
+            .is_some_and(|(a, b)| a.eq_ignore_ascii_case(b))
+}
 
-            Err(error) => {
-                match error.kind() {
-                    NotFound => Ok(
-                        Response::builder().status(404)
-                            .body(Body::empty()).unwrap()
-                    ),
-                    _ => Err(error.into()),
+// Redirects from an upstream refer to its own origin, which the client may not
+// be able to reach (and shouldn't, bypassing the proxy). So Location headers
+// that point under the upstream URI are rewritten to point under the route.
+#[derive(Clone)]
+struct LocationRewrite {
+    route: String,
+    upstream: Uri,
+    // The path under the upstream URI that the route is forwarded to.
+    prefix: String,
+}
+
+impl LocationRewrite {
+    // Rewrite an absolute URI, or a path relative to the upstream's origin.
+    // Anything else, like a relative path or a different origin, is kept.
+    fn rewrite(&self, location: &str) -> Option<String> {
+        let absolute_path = location.starts_with('/')
+            && !location.starts_with("//");
+        let path = match absolute_path {
+            true => location.to_string(),
+            false => {
+                let uri: Uri = location.parse().ok()?;
+                if uri.scheme().is_none()
+                    || !same_origin(&uri, &self.upstream)
+                {
+                    return None;
                 }
+                uri.path_and_query()
+                    .map_or_else(|| "/".to_string(), |path| path.to_string())
             },
         };
 
-        Poll::Ready(response)
+        let base = self.upstream.path().trim_end_matches('/').to_string()
+            + &self.prefix;
+        let base = base.trim_end_matches('/');
+        let rest = path.strip_prefix(base)
+            .filter(|rest| rest.is_empty() || rest.starts_with(['/', '?']))?;
+        let rewritten = self.route.trim_end_matches('/').to_string() + rest;
+        match rewritten.starts_with('/') {
+            true => Some(rewritten),
+            false => Some(format!("/{}", rewritten)),
+        }
+    }
+
+    fn apply(&self, mut response: Response<Body>) -> Response<Body> {
+        let rewritten = response.headers().get("location")
+            .and_then(|location| location.to_str().ok())
+            .and_then(|location| self.rewrite(location))
+            .and_then(|location| HeaderValue::from_str(&location).ok());
+        if let Some(location) = rewritten {
+            response.headers_mut().insert("location", location);
+        }
+
+        response
+    }
+}
+
+// Headers that only apply to a single connection, which must not be forwarded
+// by a proxy (RFC 7230, Section 6.1).
+const HOP_BY_HOP_HEADERS: &[&str] = &[
+    "connection", "keep-alive", "proxy-authenticate", "proxy-authorization",
+    "proxy-connection", "te", "trailer", "transfer-encoding", "upgrade",
+];
+
+// The protocol that a request asks to upgrade its connection to, if any.
+fn upgrade_protocol(headers: &HeaderMap) -> Option<&HeaderValue> {
+    let upgrade = headers.get_all("connection").iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|option| option.trim().eq_ignore_ascii_case("upgrade"));
+    match upgrade {
+        true => headers.get("upgrade"),
+        false => None,
+    }
+}
+
+// Headers that tell the upstream about the original request, which are set by
+// the proxy rather than forwarded as they are.
+const X_FORWARDED_HEADERS: &[&str] = &[
+    "x-forwarded-for", "x-forwarded-proto", "x-forwarded-host",
+];
+
+// The connection that a request was received on.
+#[derive(Clone, Copy, Default)]
+struct Connection {
+    // The address of the client.
+    peer: Option<SocketAddr>,
+    // Whether the connection is over TLS.
+    secure: bool,
+}
+
+// Construct the X-Forwarded-* headers for a request received on `connection`.
+// The peer is appended to any X-Forwarded-For chain set by earlier proxies.
+fn x_forwarded_headers(request: &Request<Body>, connection: Connection) ->
+    Vec<(&'static str, String)>
+{
+    let mut headers = Vec::new();
+    let mut chain: Vec<String> = request.headers()
+        .get_all("x-forwarded-for").iter()
+        .filter_map(|value| value.to_str().ok())
+        .map(|value| value.to_string())
+        .collect();
+    chain.extend(connection.peer.map(|peer| peer.ip().to_string()));
+    if !chain.is_empty() {
+        headers.push(("x-forwarded-for", chain.join(", ")));
+    }
+
+    let proto = if connection.secure { "https" } else { "http" };
+    headers.push(("x-forwarded-proto", proto.to_string()));
+    let host = request.headers().get("host")
+        .and_then(|host| host.to_str().ok())
+        .or_else(|| request.uri().authority().map(|host| host.as_str()));
+    if let Some(host) = host {
+        headers.push(("x-forwarded-host", host.to_string()));
+    }
+
+    headers
+}
+
+// Construct the Forwarded header (RFC 7239) for a request received on
+// `connection`, appending an element for this hop to any the client sent.
+fn forwarded_header(request: &Request<Body>, connection: Connection) ->
+    String
+{
+    let mut element = Vec::new();
+    match connection.peer.map(|peer| peer.ip()) {
+        // An IPv6 address must be bracketed and quoted.
+        Some(IpAddr::V6(address)) => element.push(
+            format!("for=\"[{}]\"", address)),
+        Some(address) => element.push(format!("for={}", address)),
+        None => {},
+    }
+
+    let proto = if connection.secure { "https" } else { "http" };
+    element.push(format!("proto={}", proto));
+    let host = request.headers().get("host")
+        .and_then(|host| host.to_str().ok())
+        .or_else(|| request.uri().authority().map(|host| host.as_str()));
+    if let Some(host) = host {
+        element.push(format!("host=\"{}\"", host));
     }
+
+    let mut elements: Vec<String> = request.headers()
+        .get_all("forwarded").iter()
+        .filter_map(|value| value.to_str().ok())
+        .map(|value| value.to_string())
+        .collect();
+    elements.push(element.join(";"));
+    elements.join(", ")
+}
+
+// Select the headers of a client request to send on to the upstream. The
+// Host header is left for the client to derive from the upstream URI.
+fn forwarded_headers(headers: &HeaderMap) ->
+    impl Iterator<Item = (&HeaderName, &HeaderValue)>
+{
+    // The Connection header may name additional hop-by-hop headers.
+    let connection: Vec<String> = headers.get_all("connection").iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|name| name.trim().to_ascii_lowercase())
+        .collect();
+
+    headers.iter().filter(move |(name, _)| {
+        let name = name.as_str();
+        name != "host" && !HOP_BY_HOP_HEADERS.contains(&name)
+            && !X_FORWARDED_HEADERS.contains(&name)
+            && !connection.iter().any(|listed| listed == name)
+    })
 }
 
 ///////////////////////////////////////////////////////////////////////////////
-// Service
+// Content Type
+////
+
+const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+// Guess the MIME type of a file from its extension.
+fn content_type(path: &Path) -> &'static str {
+    let extension = match path.extension().and_then(|e| e.to_str()) {
+        Some(extension) => extension.to_ascii_lowercase(),
+        None => return DEFAULT_CONTENT_TYPE,
+    };
+
+    match extension.as_str() {
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "js" | "mjs" => "application/javascript",
+        "json" | "map" => "application/json",
+        "txt" => "text/plain",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "wasm" => "application/wasm",
+        _ => DEFAULT_CONTENT_TYPE,
+    }
+}
+
+// The charset declared for text files, unless configured otherwise.
+const DEFAULT_CHARSET: &str = "utf-8";
+
+// Guess the MIME type of a file, with the configured charset if it's text.
+// Binary types never get a charset. User-defined types take precedence over
+// the built-in ones, and are used as given if they have parameters already.
+fn content_type_header(path: &Path, options: &StaticFileOptions) -> String {
+    let extension = path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+    let content_type = match extension.as_ref()
+        .and_then(|extension| options.mime_types.get(extension))
+    {
+        Some(content_type) if content_type.contains(';') =>
+            return content_type.clone(),
+        Some(content_type) => content_type.as_str(),
+        None => content_type(path),
+    };
+
+    if !content_type.starts_with("text/") {
+        return content_type.to_string();
+    }
+
+    let charset = extension
+        .and_then(|extension| options.charsets.get(&extension))
+        .unwrap_or(&options.charset);
+    match charset {
+        Some(charset) => format!("{}; charset={}", content_type, charset),
+        None => content_type.to_string(),
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// StaticFileFuture
 ////
 
+type ResponseResult = Result<Response<Body>, ProxyError>;
+
+// Files larger than this are streamed from disk instead of being read into
+// memory all at once.
+const DEFAULT_STREAM_THRESHOLD: u64 = 1024 * 1024;
+
 #[derive(Clone)]
-struct DevProxService {
-    root: PathBuf,
-    proxies: Vec<ProxyRoute>,
+struct StaticFileOptions {
+    stream_threshold: u64,
+    // Served in place of any file that doesn't exist, so that client-side
+    // routers in single-page apps can handle the path. Relative to the root.
+    fallback: Option<PathBuf>,
+    // Page to serve with 404 responses. Relative to the root.
+    not_found: Option<PathBuf>,
+    // Only fall back for requests that look like page navigations, so that
+    // missing assets still 404. The fallback defaults to the root index file.
+    spa: bool,
+    // Render a listing of directories that have no index file.
+    listing: bool,
+    // Include hidden files in directory listings.
+    show_hidden: bool,
+    // Respond 404 rather than 403 to requests for paths outside of the root,
+    // so as not to reveal whether they exist.
+    conceal_forbidden: bool,
+    // Serve files and directories whose names begin with a '.'. Otherwise,
+    // they're reported missing, so that e.g. .env and .git aren't exposed.
+    allow_hidden: bool,
+    // Whether symlinks are followed out of the root, or at all.
+    symlinks: SymlinkPolicy,
+    // The charset declared for text files, if any, and overrides of it by
+    // (lowercase) extension.
+    charset: Option<String>,
+    charsets: HashMap<String, Option<String>>,
+    // MIME types by (lowercase) extension, overriding the built-in ones.
+    mime_types: HashMap<String, String>,
+    // Compress textual files at this level, for clients that accept it.
+    compression: Option<Level>,
+    // Snippets to inject into HTML documents, like the live reload script.
+    injections: Vec<String>,
+    // Environment variables to substitute into some text files.
+    substitutions: Option<Substitutions>,
+    // Rules for the Cache-Control header, of which the first match wins.
+    cache_rules: Vec<CacheRule>,
+    // Serve a blank icon for /favicon.ico, if the root doesn't have one.
+    favicon: bool,
+    // Accept PUT and DELETE requests for files, and the largest body
+    // accepted for an upload.
+    allow_write: bool,
+    max_upload_size: u64,
+    // The names of the files served for directories, in order of preference.
+    // Static routes may have their own.
+    index: Arc<[String]>,
 }
 
-impl DevProxService {
-    pub fn new(root: PathBuf) -> Self {
-        DevProxService { root, proxies: Vec::new() }
+impl Default for StaticFileOptions {
+    fn default() -> Self {
+        Self {
+            stream_threshold: DEFAULT_STREAM_THRESHOLD, fallback: None,
+            not_found: None, spa: false, listing: false, show_hidden: false,
+            conceal_forbidden: false, allow_hidden: false,
+            symlinks: SymlinkPolicy::default(),
+            charset: Some(DEFAULT_CHARSET.to_string()),
+            charsets: HashMap::new(),
+            mime_types: HashMap::new(),
+            compression: Some(compression::DEFAULT_LEVEL),
+            injections: Vec::new(),
+            substitutions: None,
+            cache_rules: Vec::new(),
+            favicon: true,
+            allow_write: false,
+            max_upload_size: write::DEFAULT_MAX_UPLOAD_SIZE,
+            index: default_index(),
+        }
     }
+}
 
-    pub fn proxy(&mut self, proxy: ProxyRoute) {
-        self.proxies.push(proxy);
+impl StaticFileOptions {
+    // The response to a request for a path outside of the root.
+    pub fn denied(&self) -> Response<Body> {
+        match self.conceal_forbidden {
+            true => empty_response(StatusCode::NOT_FOUND),
+            false => empty_response(StatusCode::FORBIDDEN),
+        }
+    }
+}
+
+// The file is opened and read through tokio::fs, which hands the blocking
+// calls off to the runtime's blocking pool, so serving a large file doesn't
+// stall the other requests on this worker.
+struct StaticFileFuture(ServiceFuture);
+
+impl StaticFileFuture {
+    pub fn new(roots: Vec<PathBuf>, index: Arc<[String]>, path: String,
+               request: request::Parts, options: Arc<StaticFileOptions>,
+               cache: Option<Arc<FileCache>>) -> Self
+    {
+        match cache {
+            Some(cache) => Self(Box::pin(serve_cached(
+                roots, index, path, request, options, cache))),
+            None => Self(Box::pin(
+                serve_file(roots, index, path, request, options))),
+        }
+    }
+}
+
+impl Future for StaticFileFuture {
+    type Output = ResponseResult;
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context<'_>) ->
+        Poll<Self::Output>
+    {
+        self.0.as_mut().poll(context)
     }
 }
 
-impl Service<Request<Body>> for DevProxService {
-    type Response = Response<Body>;
-    type Error = ProxyError;
-    type Future = Pin<Box<dyn Future<
-            Output = Result<Self::Response, Self::Error>> + Send + Sync>>;
+fn empty_response(status: StatusCode) -> Response<Body> {
+    Response::builder().status(status).body(Body::empty()).unwrap()
+}
+
+// The methods that static files can be requested with, and those that can be
+// used when writing is allowed.
+const STATIC_METHODS: &str = "GET, HEAD, OPTIONS";
+const WRITABLE_METHODS: &str = "GET, HEAD, OPTIONS, PUT, DELETE";
+
+// A response listing the methods allowed for static files, either to answer
+// an OPTIONS request or to refuse another method.
+fn allowed_methods(status: StatusCode, writable: bool) -> Response<Body> {
+    let methods = match writable {
+        true => WRITABLE_METHODS,
+        false => STATIC_METHODS,
+    };
+    Response::builder()
+        .status(status)
+        .header("allow", methods)
+        .body(Body::empty()).unwrap()
+}
 
-    fn poll_ready(&mut self, _context: &mut Context<'_>) ->
-        Poll<Result<(), Self::Error>>
-    { Ok(()).into() }
+const INDEX_FILE: &str = "index.html";
 
-    fn call(&mut self, request: Request<Body>) -> Self::Future {
-        let path = request.uri().path();
-        if let Some(proxy) = self.proxies.iter().find(|p| p.matches(path)) {
-            return Box::pin(proxy.request(request));
+// The names of the files served for directories, unless configured otherwise.
+fn default_index() -> Arc<[String]> {
+    Arc::new([INDEX_FILE.to_string()])
+}
+
+// Canonicalize `path`, refusing anything that resolves to a file outside of
+// `root` (through "..", an absolute path, or a symlink), unless symlinks are
+// to be followed anywhere. Every component of the path is checked, so a
+// symlinked directory along the way is caught as well as a symlinked file.
+async fn resolve(root: &Path, path: &Path, symlinks: SymlinkPolicy) ->
+    io::Result<PathBuf>
+{
+    if symlinks == SymlinkPolicy::Deny {
+        let relative = path.strip_prefix(root)
+            .map_err(|_| path_escapes_root())?;
+        let mut current = root.to_path_buf();
+        for component in relative.components() {
+            current.push(component);
+            if fs::symlink_metadata(&current).await?.is_symlink() {
+                return Err(io::Error::new(io::ErrorKind::PermissionDenied,
+                                          "symlinks are not followed"));
+            }
         }
+    }
 
-        Box::pin(StaticFileFuture::new(
-            self.root.join(path.strip_prefix("/").unwrap())))
+    let resolved = fs::canonicalize(path).await?;
+    if symlinks != SymlinkPolicy::Follow && !resolved.starts_with(root) {
+        return Err(path_escapes_root());
     }
+
+    Ok(resolved)
 }
 
-///////////////////////////////////////////////////////////////////////////////
-// Main
-////
+enum Opened {
+    // The path of the file that was opened, for guessing its type.
+    File(PathBuf, fs::File),
+    // A directory without an index file, to be listed.
+    Directory(PathBuf),
+    // A directory that was requested without a trailing slash.
+    Redirect,
+}
 
-#[tokio::main]
-async fn main() {
-    let mut service = DevProxService::new(current_dir().unwrap());
-    service.proxy(ProxyRoute::new(
-        "/api".to_string(),
-        "http://localhost:3000/api".parse().unwrap()
-    ));
-    hyper::Server::bind(&"127.0.0.1:8080".parse().unwrap())
-        .serve(make_service_fn(|_: &AddrStream| {
-            let service = service.clone();
-            async move { Ok::<_, Infallible>(service) }
-        }))
-        .await
-        .unwrap();
+// Open the file at `path`, or the first of the `index` files that exists if
+// `path` is a directory. A directory has to be requested with a trailing
+// slash, so that relative links in its index resolve against it, rather than
+// against its parent.
+async fn open(root: &Path, path: PathBuf, trailing_slash: bool,
+              index: &[String], options: &StaticFileOptions) ->
+    io::Result<Opened>
+{
+    let resolved = resolve(root, &path, options.symlinks).await?;
+    if !fs::metadata(&resolved).await?.is_dir() {
+        return Ok(Opened::File(path, fs::File::open(&resolved).await?));
+    } else if !trailing_slash {
+        return Ok(Opened::Redirect);
+    }
+
+    for name in index {
+        let index = resolved.join(name);
+        match resolve(root, &index, options.symlinks).await {
+            Ok(file) =>
+                return Ok(Opened::File(index, fs::File::open(&file).await?)),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {},
+            Err(error) => return Err(error),
+        }
+    }
+
+    match options.listing {
+        true => Ok(Opened::Directory(resolved)),
+        false => Err(io::ErrorKind::NotFound.into()),
+    }
+}
+
+// Read `length` bytes of the file starting at `start` into a body.
+async fn read_body(mut file: fs::File, start: u64, length: u64,
+                   options: &StaticFileOptions) -> io::Result<Body>
+{
+    if start > 0 {
+        file.seek(io::SeekFrom::Start(start)).await?;
+    }
+
+    if length > options.stream_threshold {
+        return Ok(Body::wrap_stream(ReaderStream::new(file.take(length))));
+    }
+
+    let mut contents = Vec::with_capacity(length as usize);
+    file.take(length).read_to_end(&mut contents).await?;
+    Ok(Body::from(contents))
+}
+
+// A request for an HTML document at a path without an extension is presumed
+// to be a navigation to a route handled by a single-page app.
+fn is_navigation(path: &str, headers: &HeaderMap) -> bool {
+    let accepts_html = headers.get_all("accept").iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| value.contains("text/html"));
+    let last = path.rsplit('/').next().unwrap_or_default();
+    accepts_html && !last.contains('.')
+}
+
+// Choose the file, if any, to serve in place of one that doesn't exist.
+fn fallback(path: &str, headers: &HeaderMap, options: &StaticFileOptions) ->
+    Option<PathBuf>
+{
+    if !options.spa {
+        return options.fallback.clone();
+    } else if !is_navigation(path, headers) {
+        return None;
+    }
+
+    // By default, the index of the root directory.
+    Some(options.fallback.clone().unwrap_or_default())
+}
+
+const FAVICON_PATH: &str = "/favicon.ico";
+
+// A blank icon, served in place of a missing favicon.ico.
+const FAVICON: &[u8] = include_bytes!("favicon.ico");
+
+fn favicon(head: bool) -> Response<Body> {
+    let body = match head {
+        true => Body::empty(),
+        false => Body::from(FAVICON),
+    };
+    Response::builder().status(StatusCode::OK)
+        .header("content-type", "image/x-icon")
+        .header("content-length", FAVICON.len())
+        .header("cache-control", "max-age=86400")
+        .body(body).unwrap()
+}
+
+// Respond 404, with the custom page if one is configured and exists.
+async fn not_found(roots: &[PathBuf], options: &StaticFileOptions) ->
+    ResponseResult
+{
+    let page = match &options.not_found {
+        Some(page) => page,
+        None => return Ok(empty_response(StatusCode::NOT_FOUND)),
+    };
+
+    // The page comes from the first root that has it, like any other file.
+    let mut contents = Err(io::ErrorKind::NotFound.into());
+    for root in roots {
+        let root = match fs::canonicalize(root).await {
+            Ok(root) => root,
+            Err(_) => continue,
+        };
+        contents = match resolve(&root, &root.join(page), options.symlinks)
+            .await
+        {
+            Ok(resolved) => fs::read(resolved).await,
+            Err(error) => Err(error),
+        };
+        if contents.is_ok() {
+            break;
+        }
+    }
+
+    match contents {
+        Ok(contents) => Ok(Response::builder().status(StatusCode::NOT_FOUND)
+                           .header("content-type",
+                                   content_type_header(page, options))
+                           .header("content-length", contents.len())
+                           .body(Body::from(contents)).unwrap()),
+        Err(_) => Ok(empty_response(StatusCode::NOT_FOUND)),
+    }
+}
+
+// Open the file at `relative` under the first of `roots` that has it, which is
+// returned with it. Each root is checked for paths that escape it on its own,
+// and one that refuses the path stops the search. Roots that don't exist are
+// skipped.
+async fn open_first(roots: &[PathBuf], relative: &Path, trailing_slash: bool,
+                    index: &[String], options: &StaticFileOptions) ->
+    io::Result<(PathBuf, Opened)>
+{
+    use io::ErrorKind::*;
+
+    for root in roots {
+        let root = match fs::canonicalize(root).await {
+            Ok(root) => root,
+            Err(error) if error.kind() == NotFound => continue,
+            Err(error) => return Err(error),
+        };
+
+        let path = root.join(relative);
+        match open(&root, path, trailing_slash, index, options).await {
+            Err(error) if error.kind() == NotFound => continue,
+            result => return result.map(|opened| (root, opened)),
+        }
+    }
+
+    Err(NotFound.into())
+}
+
+// Read all of `body`, unless it's longer than `limit`, in which case what was
+// read is put back in front of the rest.
+async fn buffer(mut body: Body, limit: u64) ->
+    Result<Result<Bytes, Body>, hyper::Error>
+{
+    let mut chunks = Vec::new();
+    let mut length = 0;
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        length += chunk.len() as u64;
+        chunks.push(chunk);
+        if length > limit {
+            let read = stream::iter(chunks).map(Ok::<_, hyper::Error>);
+            return Ok(Err(Body::wrap_stream(read.chain(body))));
+        }
+    }
+
+    Ok(Ok(Bytes::from(chunks.concat())))
+}
+
+// Answer a request with a cached response, as the files would have.
+fn cached_response(mut headers: HeaderMap, contents: Bytes,
+                   request: &request::Parts) -> Response<Body>
+{
+    let etag = headers.get("etag").and_then(|etag| etag.to_str().ok());
+    let modified = headers.get("last-modified")
+        .and_then(|modified| modified.to_str().ok())
+        .and_then(|modified| httpdate::parse_http_date(modified).ok());
+    if etag.is_some_and(|etag| not_modified(&request.headers, etag, modified))
+    {
+        let mut response = empty_response(StatusCode::NOT_MODIFIED);
+        for name in ["cache-control", "vary", "etag", "last-modified"] {
+            if let Some(value) = headers.remove(name) {
+                response.headers_mut().insert(name, value);
+            }
+        }
+        return response;
+    }
+
+    headers.insert("content-length", contents.len().into());
+    let body = match request.method == Method::HEAD {
+        true => Body::empty(),
+        false => Body::from(contents),
+    };
+    let mut response = Response::new(body);
+    *response.headers_mut() = headers;
+    response
+}
+
+// Serve a request from the cache if it has the response, and the file it was
+// read from hasn't been modified since, without reading the file. Otherwise
+// it's served from the files, and the response is cached if it's whole and
+// small enough.
+async fn serve_cached(roots: Vec<PathBuf>, index: Arc<[String]>, path: String,
+                      request: request::Parts,
+                      options: Arc<StaticFileOptions>, cache: Arc<FileCache>)
+    -> ResponseResult
+{
+    if request.headers.contains_key("range") {
+        return serve_file(roots, index, path, request, options).await;
+    }
+
+    let key = file_cache::Key::new(&roots, &request);
+    if let Some((headers, contents, source)) = cache.get(&key) {
+        if source.is_current().await {
+            return Ok(cached_response(headers, contents, &request));
+        }
+    }
+
+    // Taken before the files are read, in case they change while they are.
+    let generation = cache.generation();
+    let get = request.method == Method::GET;
+    let limit = options.stream_threshold;
+    let response = serve_file(roots, index, path, request, options).await?;
+    let length = response.headers().get("content-length")
+        .and_then(|length| length.to_str().ok())
+        .and_then(|length| length.parse::<u64>().ok());
+    let too_large = length
+        .is_some_and(|length| length > limit || !cache.admits(length));
+    let source = response.extensions().get::<file_cache::Source>().cloned();
+    let cacheable = get && response.status() == StatusCode::OK && !too_large;
+    let source = match source {
+        Some(source) if cacheable => source,
+        _ => return Ok(response),
+    };
+
+    let (parts, body) = response.into_parts();
+    match buffer(body, limit).await? {
+        Ok(contents) => {
+            cache.insert(key, generation, parts.headers.clone(),
+                         contents.clone(), source);
+            Ok(Response::from_parts(parts, Body::from(contents)))
+        },
+        Err(body) => Ok(Response::from_parts(parts, body)),
+    }
+}
+
+async fn serve_file(roots: Vec<PathBuf>, index: Arc<[String]>, path: String,
+                    request: request::Parts,
+                    options: Arc<StaticFileOptions>) -> ResponseResult
+{
+    use io::ErrorKind::*;
+
+    let trailing_slash = path.ends_with('/');
+    let opened = match relative_path(&path) {
+        Ok(relative) if !options.allow_hidden && is_hidden(&relative) =>
+            Err(io::ErrorKind::NotFound.into()),
+        Ok(relative) => open_first(&roots, &relative, trailing_slash, &index,
+                                   &options).await,
+        Err(error) => Err(error),
+    };
+
+    // Browsers ask for a favicon with every page, which would otherwise 404.
+    let missing = matches!(&opened, Err(error) if error.kind() == NotFound);
+    if missing && options.favicon && request.uri.path() == FAVICON_PATH {
+        return Ok(favicon(request.method == Method::HEAD));
+    }
+
+    let headers = &request.headers;
+    let opened = match (opened, fallback(&path, headers, &options)) {
+        (Err(error), Some(fallback)) if error.kind() == NotFound =>
+            match open_first(&roots, &fallback, true, &index, &options)
+                .await
+            {
+                // Falling back to a directory only serves its index.
+                Ok((_, Opened::Directory(_))) => Err(NotFound.into()),
+                result => result,
+            },
+        (result, _) => result,
+    };
+
+    match opened {
+        Ok((root, Opened::File(file_path, file))) => {
+            let relative = file_path.strip_prefix(&root).unwrap_or(&file_path);
+            serve_opened_file(&root, relative, file, &request, &options)
+                .await
+        },
+        Ok((_, Opened::Redirect)) => {
+            let location = match request.uri.query() {
+                Some(query) => format!("{}/?{}", request.uri.path(), query),
+                None => format!("{}/", request.uri.path()),
+            };
+            Ok(Response::builder().status(StatusCode::MOVED_PERMANENTLY)
+               .header("location", location)
+               .body(Body::empty()).unwrap())
+        },
+        Ok((_, Opened::Directory(directory))) => {
+            // The listing is titled with the whole URL path, mount and all.
+            let url_path = percent_decode(request.uri.path())
+                .unwrap_or_else(|| path.clone());
+            let modified = fs::metadata(&directory).await?.modified().ok();
+            let listing = listing::render(
+                &directory, &url_path, options.show_hidden).await?;
+            let response = Response::builder().status(StatusCode::OK)
+                .header("content-type", "text/html; charset=utf-8")
+                .header("content-length", listing.len());
+            Ok(with_source(response, directory, modified)
+               .body(Body::from(listing)).unwrap())
+        },
+        Err(error) => match error.kind() {
+            NotFound => not_found(&roots, &options).await,
+            PermissionDenied => Ok(options.denied()),
+            _ => Err(error.into()),
+        },
+    }
+}
+
+// Find the precompressed copies of the file at `path` (relative to `root`),
+// like "bundle.js.gz", in the encodings the client accepts.
+async fn find_precompressed(root: &Path, path: &Path, headers: &HeaderMap,
+                            symlinks: SymlinkPolicy) ->
+    Vec<(Encoding, PathBuf)>
+{
+    let mut found = Vec::new();
+    if !headers.contains_key("accept-encoding") {
+        return found;
+    }
+
+    for encoding in compression::PRECOMPRESSED {
+        let mut sidecar = root.join(path).into_os_string();
+        sidecar.push(".");
+        sidecar.push(encoding.extension());
+        let sidecar = Path::new(&sidecar);
+        if let Ok(resolved) = resolve(root, sidecar, symlinks).await {
+            found.push((*encoding, resolved));
+        }
+    }
+
+    found
+}
+
+// How the body of a full response is encoded.
+enum Coding {
+    Identity,
+    Precompressed(Encoding),
+    OnTheFly(Encoding),
+}
+
+// Note the file that a response is read from, so that a cached copy of it can
+// be checked against the file.
+fn with_source(response: response::Builder, path: PathBuf,
+               modified: Option<SystemTime>) -> response::Builder
+{
+    match modified {
+        Some(modified) =>
+            response.extension(file_cache::Source { path, modified }),
+        None => response,
+    }
+}
+
+// Serve an opened file, whose `path` is relative to `root`.
+async fn serve_opened_file(root: &Path, path: &Path, file: fs::File,
+                           request: &request::Parts,
+                           options: &StaticFileOptions) -> ResponseResult
+{
+    // A HEAD request gets the same headers as a GET, but the file isn't read.
+    let headers = &request.headers;
+    let head = request.method == Method::HEAD;
+    let metadata = file.metadata().await?;
+    let content_type = content_type_header(path, options);
+
+    // HTML documents get the injected snippets, and some files get
+    // environment variables substituted into them, so they're always served
+    // whole, and never from a precompressed copy.
+    let injecting = !options.injections.is_empty()
+        && content_type.starts_with("text/html");
+    let substitutions = options.substitutions.as_ref()
+        .filter(|substitutions| substitutions.applies(path, &content_type));
+    let transforming = injecting || substitutions.is_some();
+
+    // Ranges refer to the unencoded file, so partial responses are never
+    // compressed. Each encoding of a file gets its own entity tag.
+    let ranged = headers.contains_key("range") && !transforming;
+    let compressible = options.compression.is_some()
+        && metadata.len() >= MIN_COMPRESS_SIZE
+        && compression::is_compressible(&content_type);
+    let precompressed = match ranged || transforming {
+        true => Vec::new(),
+        false => find_precompressed(root, path, headers, options.symlinks)
+            .await,
+    };
+
+    let available: Vec<Encoding> = precompressed.iter()
+        .map(|(encoding, _)| *encoding).collect();
+    let (file, file_path, metadata, coding) =
+        match compression::negotiate(headers, &available) {
+            Some(encoding) => {
+                let (_, sidecar_path) = precompressed.iter()
+                    .find(|(candidate, _)| *candidate == encoding).unwrap();
+                let sidecar = fs::File::open(sidecar_path).await?;
+                let metadata = sidecar.metadata().await?;
+                (sidecar, sidecar_path.clone(), metadata,
+                 Coding::Precompressed(encoding))
+            },
+            None => {
+                let encoding = match compressible && !ranged {
+                    true => compression::negotiate(
+                        headers, compression::ON_THE_FLY),
+                    false => None,
+                };
+                (file, root.join(path), metadata,
+                 encoding.map_or(Coding::Identity, Coding::OnTheFly))
+            },
+        };
+
+    let length = metadata.len();
+    let modified = metadata.modified().ok();
+    let file_tag = entity_tag(&metadata);
+    let etag = match &coding {
+        Coding::Identity => file_tag.clone(),
+        Coding::Precompressed(encoding) | Coding::OnTheFly(encoding) =>
+            variant_tag(&file_tag, encoding.name()),
+    };
+    let etag = match injecting {
+        true => variant_tag(&etag, "injected"),
+        false => etag,
+    };
+    let etag = match substitutions {
+        Some(_) => variant_tag(&etag, "substituted"),
+        None => etag,
+    };
+
+    let mut response = Response::builder().header(
+        "cache-control", cache_control(&options.cache_rules, path));
+    if compressible || !precompressed.is_empty() {
+        response = response.header("vary", "accept-encoding");
+    }
+
+    if not_modified(headers, &etag, modified) {
+        let response = response.status(StatusCode::NOT_MODIFIED)
+            .header("etag", etag);
+        return Ok(last_modified(response, modified)
+                  .body(Body::empty()).unwrap());
+    }
+
+    let response = response
+        .header("accept-ranges", if transforming { "none" } else { "bytes" })
+        .header("etag", etag);
+    let response = last_modified(response, modified);
+
+    let range = match coding {
+        Coding::Identity if !transforming =>
+            parse_range(headers.get("range"), length),
+        _ => RangeRequest::Full,
+    };
+
+    match range {
+        RangeRequest::Full => {
+            // The length of a transformed file depends on its contents, so
+            // it's read to find the length even for a HEAD request.
+            let body = match head && !transforming {
+                true => Body::empty(),
+                false => read_body(file, 0, length, options).await?,
+            };
+            let (body, length) = match transforming {
+                true => {
+                    let mut contents = hyper::body::to_bytes(body).await?
+                        .to_vec();
+                    if let Some(substitutions) = substitutions {
+                        contents = substitutions.substitute(&contents);
+                    }
+                    if injecting {
+                        contents = inject::inject(
+                            &contents, &options.injections);
+                    }
+                    let length = contents.len() as u64;
+                    match head {
+                        true => (Body::empty(), length),
+                        false => (Body::from(contents), length),
+                    }
+                },
+                false => (body, length),
+            };
+            let response = response.status(StatusCode::OK)
+                .header("content-type", content_type);
+            let response = with_source(response, file_path, modified);
+            let level = options.compression
+                .unwrap_or(compression::DEFAULT_LEVEL);
+            Ok(match coding {
+                Coding::OnTheFly(encoding) => response
+                    .header("content-encoding", encoding.name())
+                    .body(compression::compress(body, encoding, level))
+                    .unwrap(),
+                Coding::Precompressed(encoding) => response
+                    .header("content-encoding", encoding.name())
+                    .header("content-length", length)
+                    .body(body).unwrap(),
+                Coding::Identity => response.header("content-length", length)
+                    .body(body).unwrap(),
+            })
+        },
+
+        RangeRequest::Partial(range) => {
+            let body = match head {
+                true => Body::empty(),
+                false => read_body(file, range.start, range.len(), options)
+                    .await?,
+            };
+            Ok(response.status(StatusCode::PARTIAL_CONTENT)
+               .header("content-type", content_type)
+               .header("content-range", range.content_range(length))
+               .header("content-length", range.len())
+               .body(body).unwrap())
+        },
+
+        RangeRequest::Multiple(ranges) => {
+            // Each part is read from its own handle, since the parts of a
+            // large file are streamed, and so read concurrently.
+            let boundary = boundary();
+            let mut parts = Vec::with_capacity(2 * ranges.len() + 1);
+            let mut body_length = 0;
+            for (index, range) in ranges.iter().enumerate() {
+                let header = part_header(&boundary, index == 0, &content_type,
+                                         range, length);
+                body_length += header.len() as u64 + range.len();
+                parts.push(Body::from(header));
+                if !head {
+                    let file = fs::File::open(&file_path).await?;
+                    parts.push(read_body(
+                        file, range.start, range.len(), options).await?);
+                }
+            }
+
+            let closing = closing_delimiter(&boundary);
+            body_length += closing.len() as u64;
+            parts.push(Body::from(closing));
+            let body = match head {
+                true => Body::empty(),
+                false => Body::wrap_stream(stream::iter(parts).flatten()),
+            };
+            Ok(response.status(StatusCode::PARTIAL_CONTENT)
+               .header("content-type", format!(
+                   "multipart/byteranges; boundary={}", boundary))
+               .header("content-length", body_length)
+               .body(body).unwrap())
+        },
+
+        RangeRequest::Unsatisfiable => Ok(
+            response.status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header("content-type", content_type)
+                .header("content-range", format!("bytes */{}", length))
+                .body(Body::empty()).unwrap()
+        ),
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Conditional Requests
+////
+
+// Construct an entity tag from the size and modification time of a file.
+// This is cheap to compute, and changes whenever the file is rewritten.
+fn entity_tag(metadata: &Metadata) -> String {
+    let modified = metadata.modified().ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    format!("\"{:x}-{:x}\"", metadata.len(), modified.as_nanos())
+}
+
+// Derive the entity tag of an encoded variant from that of the file.
+fn variant_tag(etag: &str, encoding: &str) -> String {
+    format!("{}-{}\"", etag.trim_end_matches('"'), encoding)
+}
+
+// Determine whether an If-None-Match header matches `etag`. This uses the
+// weak comparison function, so a weak tag matches its strong counterpart.
+fn etag_matches(header: Option<&HeaderValue>, etag: &str) -> bool {
+    let header = match header.and_then(|h| h.to_str().ok()) {
+        Some(header) => header,
+        None => return false,
+    };
+
+    let etag = etag.trim_start_matches("W/");
+    header.split(',')
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+// Determine whether an If-Modified-Since header is at or after `modified`.
+// A date that can't be parsed never matches.
+fn modified_since(header: Option<&HeaderValue>, modified: Option<SystemTime>)
+                  -> bool
+{
+    let since = header.and_then(|h| h.to_str().ok())
+        .and_then(|h| httpdate::parse_http_date(h).ok());
+    match (since, modified) {
+        // HTTP dates only have a resolution of one second.
+        (Some(since), Some(modified)) => modified.duration_since(since)
+            .map(|newer| newer.as_secs() == 0).unwrap_or(true),
+        _ => false,
+    }
+}
+
+// Determine whether a conditional GET can be answered with 304. When the
+// request has an If-None-Match header, If-Modified-Since is ignored.
+fn not_modified(headers: &HeaderMap, etag: &str,
+                modified: Option<SystemTime>) -> bool
+{
+    match headers.get("if-none-match") {
+        Some(header) => etag_matches(Some(header), etag),
+        None => modified_since(headers.get("if-modified-since"), modified),
+    }
+}
+
+fn last_modified(response: response::Builder, modified: Option<SystemTime>)
+                 -> response::Builder
+{
+    match modified {
+        Some(modified) => response.header(
+            "last-modified", httpdate::fmt_http_date(modified)),
+        None => response,
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Range
+////
+
+// A range of bytes within a file. The end is inclusive, as in the header.
+#[derive(Clone, Copy)]
+struct ByteRange {
+    start: u64,
+    end: u64,
+}
+
+impl ByteRange {
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    pub fn content_range(&self, length: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, length)
+    }
+}
+
+// Requests for more ranges than this are answered with the whole file, so
+// that a small request can't be used to generate a much larger response.
+const MAX_RANGES: usize = 16;
+
+enum RangeRequest {
+    Full,
+    Partial(ByteRange),
+    Multiple(Vec<ByteRange>),
+    Unsatisfiable,
+}
+
+// Interpret one range of a Range header, for a file of `length` bytes. Returns
+// None if the range is malformed, or Some(None) if it's unsatisfiable.
+fn parse_byte_range(spec: &str, length: u64) -> Option<Option<ByteRange>> {
+    let (first, last) = spec.split_once('-')?;
+    let (first, last) = (first.trim(), last.trim());
+    if first.is_empty() {
+        // A suffix range: the final `last` bytes of the file.
+        return match last.parse::<u64>().ok()? {
+            0 => Some(None),
+            _ if length == 0 => Some(None),
+            suffix => Some(Some(ByteRange {
+                start: length.saturating_sub(suffix), end: length - 1,
+            })),
+        };
+    }
+
+    let start = first.parse::<u64>().ok()?;
+    let end = match last {
+        "" => u64::MAX,
+        last => match last.parse::<u64>() {
+            Ok(end) if end >= start => end,
+            _ => return None,
+        },
+    };
+
+    match start >= length {
+        true => Some(None),
+        false => Some(Some(ByteRange { start, end: end.min(length - 1) })),
+    }
+}
+
+// Sort ranges, and merge those that overlap or are adjacent, as RFC 9110
+// allows. Clients get each byte at most once.
+fn coalesce(mut ranges: Vec<ByteRange>) -> Vec<ByteRange> {
+    ranges.sort_by_key(|range| range.start);
+    let mut coalesced: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match coalesced.last_mut() {
+            Some(last) if range.start <= last.end.saturating_add(1) =>
+                last.end = last.end.max(range.end),
+            _ => coalesced.push(range),
+        }
+    }
+
+    coalesced
+}
+
+// Interpret the Range header of a request for a file of `length` bytes. A
+// missing or malformed header, or one asking for too many ranges, is
+// ignored, and the whole file is served. Ranges that can't be satisfied are
+// dropped, unless that leaves none.
+fn parse_range(header: Option<&HeaderValue>, length: u64) -> RangeRequest {
+    use RangeRequest::*;
+
+    let specs = match header.and_then(|h| h.to_str().ok())
+        .and_then(|h| h.trim().strip_prefix("bytes="))
+    {
+        Some(specs) => specs.split(',')
+            .map(|spec| spec.trim())
+            .filter(|spec| !spec.is_empty())
+            .collect::<Vec<&str>>(),
+        None => return Full,
+    };
+
+    if specs.is_empty() || specs.len() > MAX_RANGES {
+        return Full;
+    }
+
+    let mut ranges = Vec::with_capacity(specs.len());
+    for spec in specs {
+        match parse_byte_range(spec, length) {
+            Some(Some(range)) => ranges.push(range),
+            Some(None) => {},
+            None => return Full,
+        }
+    }
+
+    let mut ranges = coalesce(ranges);
+    match ranges.len() {
+        0 => Unsatisfiable,
+        1 => Partial(ranges.remove(0)),
+        _ => Multiple(ranges),
+    }
+}
+
+// A multipart/byteranges body is made of a header for each part, the bytes of
+// that part, and a closing delimiter.
+fn part_header(boundary: &str, first: bool, content_type: &str,
+               range: &ByteRange, length: u64) -> String
+{
+    format!("{}--{}\r\ncontent-type: {}\r\ncontent-range: {}\r\n\r\n",
+            if first { "" } else { "\r\n" }, boundary, content_type,
+            range.content_range(length))
+}
+
+fn closing_delimiter(boundary: &str) -> String {
+    format!("\r\n--{}--\r\n", boundary)
+}
+
+// The boundary only has to be absent from the parts. The time is as good as a
+// random number for that.
+fn boundary() -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    format!("dev-proxy-{:032x}", now.as_nanos())
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Paths
+////
+
+fn hex_value(digit: u8) -> Option<u8> {
+    match digit {
+        b'0'..=b'9' => Some(digit - b'0'),
+        b'a'..=b'f' => Some(digit - b'a' + 10),
+        b'A'..=b'F' => Some(digit - b'A' + 10),
+        _ => None,
+    }
+}
+
+// Encode everything but the unreserved characters of RFC 3986 (and '/') in
+// a path, so that it can be used in a URL.
+fn percent_encode(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9'
+                | b'-' | b'.' | b'_' | b'~' | b'/' =>
+                encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    encoded
+}
+
+// Decode the percent-encoded octets in a request path. Returns None if an
+// escape sequence is malformed or the decoded path isn't valid UTF-8.
+fn percent_decode(path: &str) -> Option<String> {
+    let mut bytes = path.bytes();
+    let mut decoded = Vec::with_capacity(path.len());
+    while let Some(byte) = bytes.next() {
+        if byte == b'%' {
+            let high = hex_value(bytes.next()?)?;
+            let low = hex_value(bytes.next()?)?;
+            decoded.push(high << 4 | low);
+        } else {
+            decoded.push(byte);
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+// Once decoded, an encoded '/' is indistinguishable from a separator, so it
+// could be used to smuggle one into a segment.
+fn has_encoded_separator(path: &str) -> bool {
+    path.to_ascii_lowercase().contains("%2f")
+}
+
+// Determine whether any component of a relative path is hidden.
+fn is_hidden(path: &Path) -> bool {
+    path.components().any(|component| {
+        component.as_os_str().to_string_lossy().starts_with('.')
+    })
+}
+
+// A prefix only matches on a segment boundary, so that /api doesn't capture
+// /apixyz.
+fn prefix_matches(prefix: &str, path: &str) -> bool {
+    match path.strip_prefix(prefix) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/')
+            || prefix.ends_with('/'),
+        None => false,
+    }
+}
+
+fn path_escapes_root() -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, "path escapes the root")
+}
+
+// Determine whether a URL path segment is exactly one ordinary component of a
+// filesystem path on this platform, so that joining it can't replace or climb
+// out of the path it's joined to.
+fn is_normal_component(segment: &str) -> bool {
+    let mut components = Path::new(segment).components();
+    matches!((components.next(), components.next()),
+             (Some(Component::Normal(_)), None))
+}
+
+// Convert a decoded URL path into a path relative to the root. This is purely
+// lexical: ".." may not climb above the root, and segments that a filesystem
+// could interpret as a separator or a drive prefix are refused outright,
+// whatever the platform, as is anything else that isn't a plain file name
+// here. Symlinks are checked when the path is resolved.
+fn relative_path(path: &str) -> io::Result<PathBuf> {
+    let mut relative = PathBuf::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {},
+            ".." => if !relative.pop() {
+                return Err(path_escapes_root());
+            },
+            segment if segment.contains(['\\', ':', '\0'])
+                || !is_normal_component(segment) =>
+                return Err(path_escapes_root()),
+            segment => relative.push(segment),
+        }
+    }
+
+    Ok(relative)
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// StaticRoute
+////
+
+// Directories of static files, served under a URL prefix. Each file is served
+// from the first directory that has it, so earlier ones can override files in
+// later ones.
+#[derive(Clone)]
+struct StaticRoute {
+    route: String,
+    roots: Vec<PathBuf>,
+    // The names of index files, in order of preference, if not the default.
+    index: Option<Arc<[String]>>,
+}
+
+impl StaticRoute {
+    // There must be at least one root.
+    pub fn new(route: String, roots: Vec<PathBuf>) -> Self {
+        assert!(!roots.is_empty(), "no roots for route {}", route);
+        Self { route, roots, index: None }
+    }
+
+    pub fn index(&mut self, names: Vec<String>) {
+        self.index = Some(names.into());
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        prefix_matches(&self.route, path)
+    }
+
+    // Strip the prefix from a (still encoded) URL path, leaving the path of
+    // the file under the root. A request for the prefix itself leaves an empty
+    // path, which is redirected to the root directory like any other.
+    pub fn strip<'a>(&self, path: &'a str) -> &'a str {
+        path.strip_prefix(self.route.trim_end_matches('/')).unwrap_or(path)
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Route
+////
+
+// Proxy routes are much larger than static ones, so they're boxed.
+#[derive(Clone)]
+enum Route {
+    Proxy(Box<ProxyRoute>),
+    Static(StaticRoute),
+}
+
+impl Route {
+    pub fn prefix(&self) -> &str {
+        match self {
+            Self::Proxy(proxy) => &proxy.route,
+            Self::Static(route) => &route.route,
+        }
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        match self {
+            Self::Proxy(proxy) => proxy.matches(path),
+            Self::Static(route) => route.matches(path),
+        }
+    }
+
+    pub fn is_pattern(&self) -> bool {
+        matches!(self, Self::Proxy(proxy) if proxy.is_pattern())
+    }
+
+    // Routes are ordered by the length of their prefix, so the most specific
+    // wins. Between a proxy and a directory with the same prefix, the proxy
+    // wins, and between two of a kind, the one added last. Exact routes win
+    // over all of them.
+    fn precedence(&self) -> (bool, usize, bool) {
+        let exact = matches!(self, Self::Proxy(proxy) if proxy.is_exact());
+        (exact, self.prefix().len(), matches!(self, Self::Proxy(_)))
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Service
+////
+
+type ServiceFuture =
+    Pin<Box<dyn Future<Output = ResponseResult> + Send + Sync>>;
+
+// The name of the host that a request was sent to, in lowercase, without the
+// port.
+fn host_name(request: &Request<Body>) -> Option<String> {
+    let host = request.headers().get("host")
+        .and_then(|host| host.to_str().ok())
+        .or_else(|| request.uri().host())?;
+    let name = match host.rsplit_once(':') {
+        // The colons of an IPv6 address are bracketed, so what follows the
+        // last of them is only a port if it's a number.
+        Some((name, port)) if !port.is_empty()
+            && port.bytes().all(|byte| byte.is_ascii_digit()) => name,
+        _ => host,
+    };
+    Some(name.to_ascii_lowercase())
+}
+
+struct DevProxService {
+    routes: Vec<Route>,
+    // The routes of virtual hosts, by (lowercase) host name, used in place of
+    // the others for requests to those hosts.
+    hosts: Arc<HashMap<String, Vec<Route>>>,
+    options: Arc<StaticFileOptions>,
+    // Compress proxied responses that the upstream didn't, at this level.
+    compression: Option<Level>,
+    // Responses for small static files, shared by every connection, if
+    // they're cached.
+    cache: Option<Arc<FileCache>>,
+    // Notifies pages of changes to static files, if enabled.
+    live_reload: Option<Arc<LiveReload>>,
+    // Snippets to inject into proxied HTML documents.
+    injections: Arc<Vec<String>>,
+    // Headers allowing requests from other origins, if any are allowed.
+    cors: Option<Arc<Cors>>,
+    // Credentials that requests must carry, if any.
+    auth: Option<Arc<BasicAuth>>,
+    // The route for requests that no file is found for, if any.
+    catch_all: Option<ProxyRoute>,
+    // The path of the liveness probe, if there is one.
+    health_check: Option<String>,
+    // Counts requests, for the metrics endpoint, if it's enabled.
+    metrics: Option<Arc<Metrics>>,
+}
+
+const DEFAULT_HEALTH_CHECK: &str = "/healthz";
+
+// The response to the liveness probe. Nothing else is checked, since the
+// proxy is alive if it can respond at all.
+fn health_response() -> Response<Body> {
+    Response::builder()
+        .header("content-type", "text/plain")
+        .header("cache-control", "no-store")
+        .body(Body::from("ok\n"))
+        .unwrap()
+}
+
+impl DevProxService {
+    // Serve the files in `roots` from "/", each from the first that has it.
+    // More directories can be mounted at other prefixes.
+    pub fn new(roots: Vec<PathBuf>) -> Self {
+        DevProxService {
+            routes: vec![
+                Route::Static(StaticRoute::new("/".to_string(), roots)),
+            ],
+            hosts: Arc::new(HashMap::new()),
+            options: Arc::new(StaticFileOptions::default()),
+            compression: Some(compression::DEFAULT_LEVEL),
+            cache: None,
+            live_reload: None,
+            injections: Arc::new(Vec::new()),
+            cors: None,
+            auth: None,
+            catch_all: None,
+            health_check: Some(DEFAULT_HEALTH_CHECK.to_string()),
+            metrics: None,
+        }
+    }
+
+    pub fn proxy(&mut self, proxy: ProxyRoute) {
+        self.routes.push(Route::Proxy(Box::new(proxy)));
+    }
+
+    pub fn mount(&mut self, route: StaticRoute) {
+        self.routes.push(Route::Static(route));
+    }
+
+    // Serve requests for the host `name` from `routes` alone.
+    pub fn virtual_host(&mut self, name: &str, routes: Vec<Route>) {
+        Arc::make_mut(&mut self.hosts)
+            .insert(name.to_ascii_lowercase(), routes);
+    }
+
+    pub fn file_options(&mut self, options: StaticFileOptions) {
+        self.options = Arc::new(options);
+    }
+
+    // Serve files with `extension` (matched case-insensitively) as
+    // `mime_type`, in place of any built-in type for it.
+    pub fn mime_type(&mut self, extension: &str, mime_type: &str) {
+        Arc::make_mut(&mut self.options).mime_types.insert(
+            extension.trim_start_matches('.').to_ascii_lowercase(),
+            mime_type.to_string());
+    }
+
+    pub fn compression(&mut self, level: Option<Level>) {
+        self.compression = level;
+    }
+
+    // Serve static files from `cache` when it has them.
+    pub fn cache(&mut self, cache: FileCache) {
+        self.cache = Some(Arc::new(cache));
+    }
+
+    // Serve change events to pages, and inject the script that listens for
+    // them into HTML documents.
+    pub fn live_reload(&mut self, live_reload: LiveReload) {
+        self.live_reload = Some(Arc::new(live_reload));
+        Arc::make_mut(&mut self.options).injections.push(
+            live_reload::SCRIPT.to_string());
+    }
+
+    pub fn cors(&mut self, cors: Cors) {
+        self.cors = Some(Arc::new(cors));
+    }
+
+    // Proxy requests that would otherwise get a 404 through `proxy`, whose
+    // prefix should be "/".
+    pub fn catch_all(&mut self, proxy: ProxyRoute) {
+        self.catch_all = Some(proxy);
+    }
+
+    // Challenge requests that don't carry any of the credentials.
+    pub fn basic_auth(&mut self, auth: BasicAuth) {
+        self.auth = Some(Arc::new(auth));
+    }
+
+    // Answer requests for `path` with 200, without consulting any route, or
+    // not at all if it's None.
+    pub fn health_check(&mut self, path: Option<String>) {
+        self.health_check = path;
+    }
+
+    // Count requests, and serve the counts in the Prometheus text format.
+    pub fn metrics(&mut self) {
+        self.metrics = Some(Arc::new(Metrics::default()));
+    }
+
+    // Inject `snippet` into HTML documents served from the static routes, and
+    // if `proxied`, into those from the upstreams as well.
+    pub fn inject(&mut self, snippet: String, proxied: bool) {
+        if proxied {
+            Arc::make_mut(&mut self.injections).push(snippet.clone());
+        }
+        Arc::make_mut(&mut self.options).injections.push(snippet);
+    }
+
+    // The directories that static files are served from.
+    pub fn roots(&self) -> Vec<PathBuf> {
+        self.routes.iter()
+            .chain(self.hosts.values().flatten())
+            .flat_map(|route| match route {
+                Route::Static(route) => route.roots.clone(),
+                Route::Proxy(_) => Vec::new(),
+            })
+            .collect()
+    }
+
+    // Find the route for a request to `path`, whatever order the routes were
+    // added in. An exact match is preferred, then the longest prefix, and a
+    // proxy over static files with the same prefix. Patterns are tried, in
+    // the order they were added, before falling back to static files.
+    fn route(&self, host: Option<&str>, path: &str) -> Option<&Route> {
+        let routes = host.and_then(|host| self.hosts.get(host))
+            .unwrap_or(&self.routes);
+        let best = routes.iter()
+            .filter(|route| !route.is_pattern() && route.matches(path))
+            .max_by_key(|route| route.precedence());
+        match best {
+            Some(Route::Proxy(_)) => best,
+            _ => routes.iter()
+                .find(|route| route.is_pattern() && route.matches(path))
+                .or(best),
+        }
+    }
+
+    // Route a request, returning the name of the route it took for the log.
+    fn dispatch(self: &Arc<Self>, request: Request<Body>,
+                connection: Connection) -> (String, ServiceFuture)
+    {
+        let preflight = self.cors.as_ref()
+            .and_then(|cors| cors.preflight(&request));
+        if let Some(response) = preflight {
+            return ("cors".to_string(), Box::pin(ready(Ok(response))));
+        }
+
+        if self.health_check.as_deref() == Some(request.uri().path()) {
+            return ("health".to_string(),
+                    Box::pin(ready(Ok(health_response()))));
+        }
+
+        // Preflight requests and the liveness probe don't carry credentials,
+        // but everything else is refused without them.
+        let challenge = self.auth.as_ref()
+            .and_then(|auth| auth.challenge(&request));
+        if let Some(response) = challenge {
+            return ("auth".to_string(), Box::pin(ready(Ok(response))));
+        }
+
+        if let Some(metrics) = &self.metrics {
+            if request.uri().path() == metrics::METRICS_PATH {
+                return ("metrics".to_string(),
+                        Box::pin(ready(Ok(metrics.response()))));
+            }
+        }
+
+        // A request for the capabilities of the server as a whole. The
+        // asterisk isn't a path, so it's never routed.
+        if request.method() == Method::OPTIONS && request.uri() == "*" {
+            return ("-".to_string(), Box::pin(ready(Ok(
+                allowed_methods(StatusCode::NO_CONTENT,
+                                self.options.allow_write)))));
+        }
+
+        if let Some(live_reload) = &self.live_reload {
+            if request.uri().path() == live_reload::SOCKET_PATH {
+                return ("live-reload".to_string(),
+                        Box::pin(ready(Ok(live_reload.connect(request)))));
+            }
+        }
+
+        let host = host_name(&request);
+        match (self.route(host.as_deref(), request.uri().path()),
+               &self.catch_all)
+        {
+            (Some(Route::Proxy(proxy)), _) =>
+                (proxy.route.clone(),
+                 self.proxy_request(proxy, request, connection)),
+            (Some(Route::Static(route)), Some(catch_all)) =>
+                self.static_or_proxy(route, catch_all, request, connection),
+            (Some(Route::Static(route)), None) =>
+                ("static".to_string(), self.static_request(route, request)),
+            (None, Some(catch_all)) =>
+                (catch_all.route.clone(),
+                 self.proxy_request(catch_all, request, connection)),
+            (None, None) => {
+                let response = empty_response(StatusCode::NOT_FOUND);
+                ("-".to_string(), Box::pin(ready(Ok(response))))
+            },
+        }
+    }
+
+    // Serve a request with a file from `route`, or through `proxy` if there
+    // isn't one. Only requests that can be sent twice are tried against the
+    // files first. Anything else goes straight to the upstream, besides
+    // writes to the files.
+    fn static_or_proxy(self: &Arc<Self>, route: &StaticRoute,
+                       proxy: &ProxyRoute, request: Request<Body>,
+                       connection: Connection) -> (String, ServiceFuture)
+    {
+        let writing = self.options.allow_write
+            && matches!(*request.method(), Method::PUT | Method::DELETE);
+        if writing {
+            return ("static".to_string(), self.static_request(route, request));
+        } else if !is_retryable(&request) {
+            return (proxy.route.clone(),
+                    self.proxy_request(proxy, request, connection));
+        }
+
+        let response = self.static_request(route, copy_request(&request));
+        let service = Arc::clone(self);
+        let proxy = proxy.clone();
+        ("static".to_string(), Box::pin(async move {
+            let response = response.await?;
+            match response.status() {
+                StatusCode::NOT_FOUND =>
+                    service.proxy_request(&proxy, request, connection).await,
+                _ => Ok(response),
+            }
+        }))
+    }
+
+    fn proxy_request(&self, proxy: &ProxyRoute, request: Request<Body>,
+                     connection: Connection) -> ServiceFuture
+    {
+        let level = self.compression.filter(|_| proxy.compresses());
+        // HEAD responses have no body to compress, or inject snippets into.
+        let head = request.method() == Method::HEAD;
+        let injections = match head {
+            true => None,
+            false => Some(self.injections.clone())
+                .filter(|injections| !injections.is_empty()),
+        };
+        if level.is_none() && injections.is_none() {
+            return Box::pin(proxy.request(request, connection));
+        }
+
+        let encoding = match head {
+            true => None,
+            false => compression::negotiate(
+                request.headers(), compression::ON_THE_FLY),
+        };
+        let limit = self.options.stream_threshold;
+        let response = proxy.request(request, connection);
+        Box::pin(async move {
+            let mut response = response.await?;
+            if let Some(injections) = injections {
+                response = inject::inject_response(
+                    response, &injections, limit).await?;
+            }
+
+            Ok(match level {
+                Some(level) => compression::compress_response(
+                    response, encoding, level),
+                None => response,
+            })
+        })
+    }
+
+    fn static_request(&self, route: &StaticRoute, request: Request<Body>) ->
+        ServiceFuture
+    {
+        let writable = self.options.allow_write;
+        match *request.method() {
+            Method::GET | Method::HEAD => {},
+            Method::PUT | Method::DELETE if writable => {},
+            Method::OPTIONS => return Box::pin(ready(Ok(
+                allowed_methods(StatusCode::NO_CONTENT, writable)))),
+            _ => return Box::pin(ready(Ok(
+                allowed_methods(StatusCode::METHOD_NOT_ALLOWED, writable)))),
+        }
+
+        let path = request.uri().path();
+        if has_encoded_separator(path) {
+            return Box::pin(ready(Ok(self.options.denied())));
+        }
+
+        let path = match percent_decode(route.strip(path)) {
+            Some(path) => path,
+            None => return Box::pin(
+                ready(Ok(empty_response(StatusCode::BAD_REQUEST)))),
+        };
+
+        // Files are only ever written to the first root.
+        let root = route.roots[0].clone();
+        let options = self.options.clone();
+        match *request.method() {
+            Method::PUT => return Box::pin(
+                write::put(root, path, request, options)),
+            Method::DELETE => return Box::pin(
+                write::delete(root, path, options)),
+            _ => {},
+        }
+
+        let (request, _) = request.into_parts();
+        let index = route.index.clone()
+            .unwrap_or_else(|| self.options.index.clone());
+        Box::pin(StaticFileFuture::new(
+            route.roots.clone(), index, path, request, options,
+            self.cache.clone()))
+    }
+
+    // Serve a request received on `connection`. Every request is logged once
+    // its response is ready, with the route that handled it: the prefix of a
+    // proxy, or "static".
+    pub fn respond(self: &Arc<Self>, request: Request<Body>,
+                   connection: Connection) -> ServiceFuture
+    {
+        let start = Instant::now();
+        let method = request.method().clone();
+        let path = request.uri().path().to_string();
+        let origin = request.headers().get("origin").cloned();
+        let (route, response) = self.dispatch(request, connection);
+        let cors = self.cors.clone();
+        let metrics = self.metrics.clone();
+        Box::pin(async move {
+            let mut response = response.await;
+            if let (Some(cors), Ok(response)) = (cors, &mut response) {
+                cors.apply(origin, response);
+            }
+
+            if let Some(metrics) = metrics {
+                metrics.record(&route, response.as_ref().ok()
+                               .map(|response| response.status()),
+                               start.elapsed());
+            }
+
+            let status = match &response {
+                Ok(response) => response.status().as_u16().to_string(),
+                Err(_) => "error".to_string(),
+            };
+            log::info!(target: "access", "{} {} {} {} {:.1?}",
+                       method, path, status, route, start.elapsed());
+            response
+        })
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Main
+////
+
+// The config file may be given as the first argument. Otherwise, the default
+// config file is used if it exists in the current directory.
+fn load_config(args: &Args) -> Result<Config, ConfigError> {
+    match &args.config {
+        Some(path) => Config::load(path),
+        None if Path::new(DEFAULT_CONFIG_FILE).exists() =>
+            Config::load(Path::new(DEFAULT_CONFIG_FILE)),
+        None => Ok(Config::default()),
+    }
+}
+
+// An empty charset in the config file means that none is declared.
+fn charset(value: &str) -> Option<String> {
+    Some(value.to_string()).filter(|value| !value.is_empty())
+}
+
+fn proxy_route(route: &RouteConfig) -> Result<ProxyRoute, ConfigError> {
+    let mut proxy = match (&route.pattern, route.prefix.is_empty()) {
+        (Some(pattern), true) => ProxyRoute::with_pattern(
+            pattern.clone(), route.upstream.clone()),
+        (None, false) => ProxyRoute::new(
+            route.prefix.clone(), route.upstream.clone()),
+        _ => return Err(ConfigError::Route(
+            "give either a prefix or a pattern".to_string())),
+    };
+    if route.exact {
+        proxy.exact(route.trailing_slash);
+    }
+    if let Some(tls) = tls::client_config(route)? {
+        proxy.tls(tls);
+    }
+    proxy.compression(route.compression);
+    if let Some(timeout) = route.timeout_ms {
+        proxy.timeout(Duration::from_millis(timeout));
+    }
+    if let Some(delay) = route.delay_ms {
+        proxy.delay(Duration::from_millis(delay));
+    }
+    if route.retries > 0 {
+        proxy.retries(route.retries, route.retry_backoff_ms
+                      .map_or(DEFAULT_RETRY_BACKOFF, Duration::from_millis));
+    }
+    proxy.strip_prefix(route.strip_prefix && !route.preserve_prefix);
+    if let Some(replacement) = &route.replace_prefix {
+        proxy.replace_prefix(replacement.clone());
+    }
+    match (route.preserve_host, &route.host_header) {
+        (false, None) => {},
+        (true, None) => proxy.host(HostPolicy::Preserve),
+        (false, Some(host)) => proxy.host(HostPolicy::Override(
+            HeaderValue::from_str(host).map_err(|error| ConfigError::Route(
+                format!("invalid host_header {:?}: {}", host, error)))?)),
+        (true, Some(_)) => return Err(ConfigError::Route(
+            "give either preserve_host or host_header".to_string())),
+    }
+    proxy.forwarded(route.forwarded);
+    if route.error_rate != 0.0 {
+        if !(0.0..=1.0).contains(&route.error_rate) {
+            return Err(ConfigError::Route(format!(
+                "error_rate must be between 0 and 1, not {}",
+                route.error_rate)));
+        }
+        let status = StatusCode::from_u16(route.error_status)
+            .map_err(|error| ConfigError::Route(format!(
+                "invalid error_status {}: {}", route.error_status, error)))?;
+        proxy.inject_faults(FaultInjection::new(
+            route.error_rate, status, route.error_seed));
+    }
+    for rule in &route.rewrites {
+        proxy.rewrite(rule.clone());
+    }
+    for (name, value) in &route.add_request_headers {
+        proxy.add_request_header(name.clone(), value.clone());
+    }
+    for (name, value) in &route.add_response_headers {
+        proxy.add_response_header(name.clone(), value.clone());
+    }
+    Ok(proxy)
+}
+
+fn static_route(mount: &MountConfig) -> StaticRoute {
+    let mut route = StaticRoute::new(mount.prefix.clone(), mount.root.clone());
+    if let Some(index) = &mount.index {
+        route.index(index.clone());
+    }
+    route
+}
+
+fn build_service(config: &Config) -> Result<DevProxService, ConfigError> {
+    let mut service = DevProxService::new(config.root.clone());
+    for route in &config.routes {
+        service.proxy(proxy_route(route)?);
+    }
+
+    for mount in &config.mounts {
+        service.mount(static_route(mount));
+    }
+
+    for host in &config.hosts {
+        let mut routes = Vec::new();
+        if !host.root.is_empty() {
+            routes.push(Route::Static(
+                StaticRoute::new("/".to_string(), host.root.clone())));
+        }
+        for route in &host.routes {
+            routes.push(Route::Proxy(Box::new(proxy_route(route)?)));
+        }
+        for mount in &host.mounts {
+            routes.push(Route::Static(static_route(mount)));
+        }
+        service.virtual_host(&host.name, routes);
+    }
+
+    let compression = config.compression.then(|| {
+        config.compression_level
+            .map_or(compression::DEFAULT_LEVEL, Level::Precise)
+    });
+
+    let defaults = StaticFileOptions::default();
+    service.file_options(StaticFileOptions {
+        stream_threshold: config.stream_threshold
+            .unwrap_or(defaults.stream_threshold),
+        fallback: config.fallback.clone(),
+        not_found: config.not_found.clone(),
+        spa: config.spa,
+        listing: config.listing,
+        show_hidden: config.show_hidden,
+        conceal_forbidden: config.conceal_forbidden,
+        allow_hidden: config.allow_hidden,
+        symlinks: config.symlinks,
+        charset: charset(config.charset.as_deref().unwrap_or(DEFAULT_CHARSET)),
+        charsets: config.charsets.iter()
+            .map(|(extension, value)| (extension.to_ascii_lowercase(),
+                                       charset(value)))
+            .collect(),
+        mime_types: HashMap::new(),
+        compression,
+        injections: Vec::new(),
+        substitutions: match config.substitute.is_empty() {
+            true => None,
+            false => Some(Substitutions::from_env(
+                config.substitute.clone(),
+                config.substitute_prefix.as_deref()
+                    .unwrap_or(substitute::DEFAULT_PREFIX))),
+        },
+        cache_rules: config.cache_rules.clone(),
+        favicon: config.favicon,
+        allow_write: config.allow_write,
+        max_upload_size: config.max_upload_size
+            .unwrap_or(defaults.max_upload_size),
+        index: config.index.clone().map_or_else(default_index, Into::into),
+    });
+    for (extension, mime_type) in &config.mime_types {
+        service.mime_type(extension, mime_type);
+    }
+    service.compression(compression);
+
+    service.health_check(Some(config.health_check.clone())
+                         .filter(|path| !path.is_empty()));
+    if config.metrics {
+        service.metrics();
+    }
+
+    if !config.cors_origins.is_empty() {
+        service.cors(Cors::new(
+            config.cors_origins.clone(), config.cors_credentials));
+    }
+
+    let mut credentials = config.basic_auth.clone();
+    if let Some(path) = &config.basic_auth_file {
+        let text = std::fs::read_to_string(path)
+            .map_err(|error| ConfigError::Io(path.clone(), error))?;
+        credentials.extend(BasicAuth::parse(&text));
+    }
+    if !credentials.is_empty() {
+        service.basic_auth(
+            BasicAuth::new(credentials).map_err(ConfigError::Auth)?);
+    }
+
+    if let Some(route) = &config.catch_all {
+        if !route.prefix.is_empty() || route.pattern.is_some() {
+            return Err(ConfigError::Route(
+                "the catch-all route takes no prefix or pattern".to_string()));
+        }
+        let mut route = route.clone();
+        route.prefix = "/".to_string();
+        service.catch_all(proxy_route(&route)?);
+    }
+
+    if let Some(snippet) = &config.inject {
+        service.inject(snippet.clone(), config.inject_proxied);
+    }
+
+    if let Some(path) = &config.inject_file {
+        let snippet = std::fs::read_to_string(path)
+            .map_err(|error| ConfigError::Io(path.clone(), error))?;
+        service.inject(snippet, config.inject_proxied);
+    }
+
+    // Files are still served, if not cached, when they can't be watched.
+    if let Some(limit) = config.cache_size.filter(|limit| *limit > 0) {
+        match FileCache::watch(limit, &service.roots()) {
+            Ok(cache) => service.cache(cache),
+            Err(error) => log::warn!(
+                "could not watch for changes to cache files: {}", error),
+        }
+    }
+
+    // Pages are still served without live reload if it can't be set up.
+    if config.live_reload {
+        match LiveReload::watch(&service.roots()) {
+            Ok(live_reload) => service.live_reload(live_reload),
+            Err(error) => log::warn!(
+                "could not watch for changes: {}", error),
+        }
+    }
+
+    Ok(service)
+}
+
+// Wait a moment after failing to accept a connection, before trying again.
+// Errors like running out of file descriptors pass with time.
+async fn accept_backoff(error: io::Error) {
+    log::warn!("could not accept connection: {}", error);
+    time::sleep(Duration::from_millis(100)).await;
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+    let mut config = load_config(&args).unwrap_or_else(|error| {
+        eprintln!("dev-prox: {}", error);
+        process::exit(1);
+    });
+    args.apply(&mut config);
+
+    // RUST_LOG overrides the configured level, e.g. to quiet a single module.
+    env_logger::Builder::new()
+        .filter_level(config.log_level)
+        .parse_default_env()
+        .init();
+
+    let service = build_service(&config).unwrap_or_else(|error| {
+        eprintln!("dev-prox: {}", error);
+        process::exit(1);
+    });
+    let service = ReloadableService::new(service);
+
+    // Everything but the address, TLS and the log level can be reloaded.
+    #[cfg(unix)]
+    tokio::spawn(reload::reload_on_hangup(service.clone()));
+
+    let acceptor = tls::acceptor(&config).unwrap_or_else(|error| {
+        eprintln!("dev-prox: {}", error);
+        process::exit(1);
+    });
+    // The server in front of the socket is expected to handle TLS.
+    #[cfg(unix)]
+    if let Some(path) = &config.unix {
+        if acceptor.is_some() {
+            eprintln!("dev-prox: TLS can't be served on a Unix socket");
+            process::exit(1);
+        }
+        unix::serve(path, service).await.unwrap_or_else(|error| {
+            eprintln!("dev-prox: {}: {}", path.display(), error);
+            process::exit(1);
+        });
+        return;
+    }
+
+    if let Some(acceptor) = acceptor {
+        tls::serve(config.bind, acceptor, service).await.unwrap();
+        return;
+    }
+
+    hyper::Server::bind(&config.bind)
+        .serve(make_service_fn(|stream: &AddrStream| {
+            let mut service = service.clone();
+            service.peer(stream.remote_addr());
+            async move { Ok::<_, Infallible>(service) }
+        }))
+        .await
+        .unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core::future::ready;
+
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use hyper::{body::Bytes, http::response};
+    use tokio::net::TcpListener;
+
+    // Serve the files in `root`.
+    fn static_service(root: &Path) -> DevProxService {
+        DevProxService::new(vec![root.to_path_buf()])
+    }
+
+    // Send `request` to `service`, and read the whole response.
+    async fn send(service: &Arc<DevProxService>, request: Request<Body>) ->
+        (response::Parts, Bytes)
+    {
+        let response = service.respond(request, Connection::default())
+            .await.unwrap();
+        let (parts, body) = response.into_parts();
+        (parts, hyper::body::to_bytes(body).await.unwrap())
+    }
+
+    async fn get(service: &Arc<DevProxService>, path: &str) -> Bytes {
+        let request = Request::get(path).body(Body::empty()).unwrap();
+        send(service, request).await.1
+    }
+
+    // A directory holding `files`, by their paths under it.
+    fn root_with(files: &[(&str, &[u8])]) -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        for (path, contents) in files {
+            let path = root.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        root
+    }
+
+    #[tokio::test]
+    async fn binary_files_are_served_byte_for_byte() {
+        let blob: Vec<u8> = (0..=255u8).rev().chain(0..=255u8).collect();
+        let root = root_with(&[("blob.bin", &blob)]);
+        let service = Arc::new(static_service(root.path()));
+        assert_eq!(get(&service, "/blob.bin").await, blob);
+    }
+
+    #[test]
+    fn content_types_are_guessed_from_extensions() {
+        let types = [
+            ("index.html", "text/html"),
+            ("style.css", "text/css"),
+            ("app.js", "application/javascript"),
+            ("module.mjs", "application/javascript"),
+            ("data.json", "application/json"),
+            ("app.js.map", "application/json"),
+            ("logo.svg", "image/svg+xml"),
+            ("photo.png", "image/png"),
+            ("photo.JPG", "image/jpeg"),
+            ("font.woff2", "font/woff2"),
+            ("app.wasm", "application/wasm"),
+        ];
+        for (path, expected) in types {
+            assert_eq!(content_type(Path::new(path)), expected, "{}", path);
+        }
+    }
+
+    #[test]
+    fn unknown_extensions_are_octet_streams() {
+        assert_eq!(content_type(Path::new("archive.xyz")),
+                   "application/octet-stream");
+        assert_eq!(content_type(Path::new("Makefile")),
+                   "application/octet-stream");
+    }
+
+    #[tokio::test]
+    async fn stylesheets_are_served_as_css() {
+        let root = root_with(&[("foo.css", b"p {}")]);
+        let service = Arc::new(static_service(root.path()));
+        let request = Request::get("/foo.css").body(Body::empty()).unwrap();
+        let (parts, _) = send(&service, request).await;
+        assert_eq!(parts.headers["content-type"], "text/css; charset=utf-8");
+    }
+
+    #[tokio::test]
+    async fn content_length_counts_bytes() {
+        // Twelve characters, in fifteen bytes.
+        let text = "crème brûlée";
+        let root = root_with(&[("menu.txt", text.as_bytes())]);
+        let service = Arc::new(static_service(root.path()));
+        let request = Request::get("/menu.txt").body(Body::empty()).unwrap();
+        let (parts, body) = send(&service, request).await;
+        assert_eq!(parts.headers["content-length"], "15");
+        assert_eq!(body, text);
+    }
+
+    // Start an upstream that answers every request with `respond`, returning
+    // its URI.
+    fn upstream<F>(respond: F) -> Uri
+    where F: Fn(Request<Body>) -> Response<Body> + Clone + Send + Sync
+        + 'static,
+    {
+        let server = hyper::Server::bind(&"127.0.0.1:0".parse().unwrap())
+            .serve(make_service_fn(move |_| {
+                let respond = respond.clone();
+                async move {
+                    Ok::<_, Infallible>(hyper::service::service_fn(
+                        move |request| ready(
+                            Ok::<_, Infallible>(respond(request)))))
+                }
+            }));
+        let uri = format!("http://{}", server.local_addr()).parse().unwrap();
+        tokio::spawn(server);
+        uri
+    }
+
+    // Serve the files in `root`, and proxy `prefix` to `upstream`.
+    fn proxy_service(root: &Path, prefix: &str, upstream: Uri) ->
+        DevProxService
+    {
+        let mut service = static_service(root);
+        service.proxy(ProxyRoute::new(prefix.to_string(), vec![upstream]));
+        service
+    }
+
+    #[tokio::test]
+    async fn serving_a_large_file_doesnt_hold_up_other_requests() {
+        let large = vec![b'x'; 64 * 1024 * 1024];
+        let root = root_with(&[("large.bin", &large)]);
+        let upstream = upstream(|_| Response::new(Body::from("pong")));
+        let service = Arc::new(proxy_service(root.path(), "/api", upstream));
+
+        let proxied = async {
+            let start = Instant::now();
+            assert_eq!(get(&service, "/api/ping").await, "pong");
+            start.elapsed()
+        };
+        let (file, elapsed) = tokio::join!(get(&service, "/large.bin"),
+                                           proxied);
+        assert_eq!(file.len(), large.len());
+        assert!(elapsed < Duration::from_secs(1), "{:?}", elapsed);
+    }
+
+    // Send a GET request for `path`, returning the status and body.
+    async fn status_of(service: &Arc<DevProxService>, path: &str) ->
+        (StatusCode, Bytes)
+    {
+        let request = Request::get(path).body(Body::empty()).unwrap();
+        let (parts, body) = send(service, request).await;
+        (parts.status, body)
+    }
+
+    #[tokio::test]
+    async fn paths_cant_climb_out_of_the_root() {
+        // Each path leads to a secret that exists, above the root.
+        let parent = root_with(&[("Cargo.toml", b"secret"),
+                                 ("www/Cargo.toml", b"secret"),
+                                 ("www/site/a/index.html", b"hello")]);
+        let root = parent.path().join("www/site");
+        let service = Arc::new(static_service(&root));
+        for path in ["/..%2f..%2fCargo.toml", "/../Cargo.toml",
+                     "/%2e%2e/Cargo.toml", "/a/../../Cargo.toml"]
+        {
+            let (status, body) = status_of(&service, path).await;
+            assert_eq!(status, StatusCode::FORBIDDEN, "{}", path);
+            assert!(body.is_empty(), "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn directories_are_served_their_index() {
+        let root = root_with(&[("index.html", b"home"),
+                               ("subdir/index.html", b"sub")]);
+        let service = Arc::new(static_service(root.path()));
+        assert_eq!(status_of(&service, "/").await,
+                   (StatusCode::OK, Bytes::from("home")));
+        assert_eq!(status_of(&service, "/subdir/").await,
+                   (StatusCode::OK, Bytes::from("sub")));
+    }
+
+    #[tokio::test]
+    async fn directories_without_an_index_are_missing() {
+        let root = root_with(&[("empty/file.txt", b"")]);
+        let service = Arc::new(static_service(root.path()));
+        assert_eq!(status_of(&service, "/empty/").await.0,
+                   StatusCode::NOT_FOUND);
+    }
+
+    // Send a GET request for `path` with `headers`.
+    async fn get_with(service: &Arc<DevProxService>, path: &str,
+                      headers: &[(&str, &str)]) -> (response::Parts, Bytes)
+    {
+        let mut request = Request::get(path);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        send(service, request.body(Body::empty()).unwrap()).await
+    }
+
+    #[tokio::test]
+    async fn ranges_of_files_are_served() {
+        let contents: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+        let root = root_with(&[("video.mp4", &contents)]);
+        let service = Arc::new(static_service(root.path()));
+
+        let (parts, body) = get_with(&service, "/video.mp4", &[]).await;
+        assert_eq!(parts.status, StatusCode::OK);
+        assert_eq!(parts.headers["accept-ranges"], "bytes");
+        assert_eq!(body, contents);
+
+        let ranges = [
+            ("bytes=0-9", "bytes 0-9/1000", &contents[..10]),
+            ("bytes=-500", "bytes 500-999/1000", &contents[500..]),
+            ("bytes=100-", "bytes 100-999/1000", &contents[100..]),
+        ];
+        for (range, content_range, expected) in ranges {
+            let (parts, body) = get_with(
+                &service, "/video.mp4", &[("range", range)]).await;
+            assert_eq!(parts.status, StatusCode::PARTIAL_CONTENT, "{}", range);
+            assert_eq!(parts.headers["content-range"], content_range);
+            assert_eq!(body, expected, "{}", range);
+        }
+
+        let (parts, _) = get_with(
+            &service, "/video.mp4", &[("range", "bytes=1000-")]).await;
+        assert_eq!(parts.status, StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(parts.headers["content-range"], "bytes */1000");
+    }
+
+    #[tokio::test]
+    async fn matching_etags_are_not_modified() {
+        let root = root_with(&[("app.js", b"one")]);
+        let service = Arc::new(static_service(root.path()));
+        let (parts, _) = get_with(&service, "/app.js", &[]).await;
+        let etag = parts.headers["etag"].to_str().unwrap().to_string();
+
+        let (parts, body) = get_with(
+            &service, "/app.js", &[("if-none-match", &etag)]).await;
+        assert_eq!(parts.status, StatusCode::NOT_MODIFIED);
+        assert_eq!(parts.headers["etag"], etag.as_str());
+        assert!(body.is_empty());
+
+        let weak = format!("W/{}", etag);
+        let (parts, _) = get_with(
+            &service, "/app.js", &[("if-none-match", &weak)]).await;
+        assert_eq!(parts.status, StatusCode::NOT_MODIFIED);
+
+        let (parts, body) = get_with(
+            &service, "/app.js", &[("if-none-match", "\"stale\"")]).await;
+        assert_eq!(parts.status, StatusCode::OK);
+        assert_eq!(body, "one");
+    }
+
+    #[tokio::test]
+    async fn changed_files_get_a_new_etag() {
+        let root = root_with(&[("app.js", b"one")]);
+        let service = Arc::new(static_service(root.path()));
+        let (parts, _) = get_with(&service, "/app.js", &[]).await;
+        let etag = parts.headers["etag"].to_str().unwrap().to_string();
+
+        std::fs::write(root.path().join("app.js"), "three").unwrap();
+        let (parts, body) = get_with(
+            &service, "/app.js", &[("if-none-match", &etag)]).await;
+        assert_eq!(parts.status, StatusCode::OK);
+        assert_ne!(parts.headers["etag"], etag.as_str());
+        assert_eq!(body, "three");
+    }
+
+    // Serve the files in `root`, falling back to `fallback` for missing ones.
+    fn fallback_service(root: &Path, fallback: &str) -> DevProxService {
+        let mut service = static_service(root);
+        service.file_options(StaticFileOptions {
+            fallback: Some(PathBuf::from(fallback)),
+            ..StaticFileOptions::default()
+        });
+        service
+    }
+
+    #[tokio::test]
+    async fn missing_paths_fall_back_to_the_app_shell() {
+        let root = root_with(&[("index.html", b"shell")]);
+        let service = Arc::new(fallback_service(root.path(), "index.html"));
+        assert_eq!(status_of(&service, "/nonexistent/deep/path").await,
+                   (StatusCode::OK, Bytes::from("shell")));
+    }
+
+    #[tokio::test]
+    async fn a_missing_fallback_is_missing() {
+        let root = root_with(&[("app.js", b"")]);
+        let service = Arc::new(fallback_service(root.path(), "index.html"));
+        assert_eq!(status_of(&service, "/users/42").await.0,
+                   StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn proxied_routes_dont_fall_back() {
+        let root = root_with(&[("index.html", b"shell")]);
+        let upstream = upstream(|_| {
+            Response::builder().status(StatusCode::NOT_FOUND)
+                .body(Body::from("upstream")).unwrap()
+        });
+        let mut service = fallback_service(root.path(), "index.html");
+        service.proxy(ProxyRoute::new("/api".to_string(), vec![upstream]));
+        let service = Arc::new(service);
+        assert_eq!(status_of(&service, "/api/users/42").await,
+                   (StatusCode::NOT_FOUND, Bytes::from("upstream")));
+    }
+
+    // Answer with the path and query that were asked for.
+    fn echo_target(request: Request<Body>) -> Response<Body> {
+        let target = request.uri().path_and_query()
+            .map(|target| target.to_string())
+            .unwrap_or_default();
+        Response::new(Body::from(target))
+    }
+
+    #[tokio::test]
+    async fn query_strings_reach_the_upstream_intact() {
+        let root = tempfile::tempdir().unwrap();
+        let service = Arc::new(
+            proxy_service(root.path(), "/api", upstream(echo_target)));
+        assert_eq!(get(&service, "/api/search?q=hello").await,
+                   "/search?q=hello");
+        assert_eq!(get(&service, "/api/search?q=a%20b&tag=%2F").await,
+                   "/search?q=a%20b&tag=%2F");
+    }
+
+    #[tokio::test]
+    async fn unmodified_files_are_not_modified_since() {
+        let root = root_with(&[("app.js", b"one")]);
+        let service = Arc::new(static_service(root.path()));
+        let (parts, _) = get_with(&service, "/app.js", &[]).await;
+        let modified = parts.headers["last-modified"].to_str().unwrap()
+            .to_string();
+        let etag = parts.headers["etag"].to_str().unwrap().to_string();
+        assert!(httpdate::parse_http_date(&modified).is_ok());
+
+        let (parts, body) = get_with(
+            &service, "/app.js", &[("if-modified-since", &modified)]).await;
+        assert_eq!(parts.status, StatusCode::NOT_MODIFIED);
+        assert!(body.is_empty());
+
+        let (parts, _) = get_with(
+            &service, "/app.js",
+            &[("if-modified-since", "Thu, 01 Jan 1970 00:00:00 GMT")]).await;
+        assert_eq!(parts.status, StatusCode::OK);
+
+        let (parts, body) = get_with(
+            &service, "/app.js", &[("if-modified-since", "yesterday")]).await;
+        assert_eq!(parts.status, StatusCode::OK);
+        assert_eq!(body, "one");
+
+        // The ETag wins, whether or not the date agrees.
+        let (parts, _) = get_with(
+            &service, "/app.js", &[("if-none-match", "\"stale\""),
+                                   ("if-modified-since", &modified)]).await;
+        assert_eq!(parts.status, StatusCode::OK);
+        let (parts, _) = get_with(
+            &service, "/app.js",
+            &[("if-none-match", &etag),
+              ("if-modified-since", "Thu, 01 Jan 1970 00:00:00 GMT")]).await;
+        assert_eq!(parts.status, StatusCode::NOT_MODIFIED);
+    }
+
+    // Answer with the headers that were sent, one per line.
+    fn echo_headers(request: Request<Body>) -> Response<Body> {
+        let headers: String = request.headers().iter()
+            .map(|(name, value)| format!(
+                "{}: {}\n", name, value.to_str().unwrap()))
+            .collect();
+        Response::new(Body::from(headers))
+    }
+
+    #[tokio::test]
+    async fn request_headers_are_forwarded() {
+        let root = tempfile::tempdir().unwrap();
+        let service = Arc::new(
+            proxy_service(root.path(), "/api", upstream(echo_headers)));
+        let (_, body) = get_with(&service, "/api/me", &[
+            ("authorization", "Bearer secret"),
+            ("cookie", "session=1"),
+            ("connection", "x-hop"),
+            ("x-hop", "1"),
+            ("proxy-connection", "keep-alive"),
+        ]).await;
+        let headers = String::from_utf8(body.to_vec()).unwrap();
+        assert!(headers.contains("authorization: Bearer secret\n"));
+        assert!(headers.contains("cookie: session=1\n"));
+        assert!(!headers.contains("x-hop"), "{}", headers);
+        assert!(!headers.contains("proxy-connection"), "{}", headers);
+    }
+
+    #[tokio::test]
+    async fn directories_are_only_listed_when_enabled() {
+        let root = root_with(&[("docs/readme.txt", b"")]);
+        let service = Arc::new(static_service(root.path()));
+        assert_eq!(status_of(&service, "/docs/").await.0,
+                   StatusCode::NOT_FOUND);
+
+        let mut service = static_service(root.path());
+        service.file_options(StaticFileOptions {
+            listing: true, ..StaticFileOptions::default()
+        });
+        let service = Arc::new(service);
+        let (parts, body) = get_with(&service, "/docs/", &[]).await;
+        assert_eq!(parts.status, StatusCode::OK);
+        assert!(parts.headers["content-type"].to_str().unwrap()
+                .starts_with("text/html"));
+        assert!(String::from_utf8_lossy(&body).contains("readme.txt"));
+    }
+
+    #[tokio::test]
+    async fn unreachable_upstreams_are_bad_gateways() {
+        // Nothing listens on the port once the listener is dropped.
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap()
+            .local_addr().unwrap().port();
+        let upstream = format!("http://127.0.0.1:{}", port).parse().unwrap();
+        let root = tempfile::tempdir().unwrap();
+        let service = Arc::new(proxy_service(root.path(), "/api", upstream));
+        assert_eq!(status_of(&service, "/api/ping").await.0,
+                   StatusCode::BAD_GATEWAY);
+    }
+
+    #[test]
+    fn nasty_paths_cant_escape_the_root() {
+        let paths = [
+            "/..", "/../etc/passwd", "/a/../../etc/passwd",
+            "/a/./b/../../../etc/passwd", "/..\\..\\etc\\passwd",
+            "/a\\..\\..\\secret", "/\0/etc/passwd",
+        ];
+        for path in paths {
+            let error = relative_path(path).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::PermissionDenied,
+                       "{:?}", path);
+        }
+    }
+
+    #[test]
+    fn absolute_looking_segments_stay_under_the_root() {
+        assert_eq!(relative_path("//etc/passwd").unwrap(),
+                   PathBuf::from("etc/passwd"));
+        assert_eq!(relative_path("/a/b/../c").unwrap(),
+                   PathBuf::from("a/c"));
+        assert_eq!(relative_path("/a/./b/").unwrap(), PathBuf::from("a/b"));
+    }
+
+    #[tokio::test]
+    async fn escapes_can_be_concealed_as_missing() {
+        let parent = root_with(&[("secret.txt", b"secret"),
+                                 ("site/index.html", b"hello")]);
+        let mut service = static_service(&parent.path().join("site"));
+        service.file_options(StaticFileOptions {
+            conceal_forbidden: true, ..StaticFileOptions::default()
+        });
+        let service = Arc::new(service);
+        for path in ["/%2e%2e%2fsecret.txt", "/%2e%2e/secret.txt",
+                     "/..%5csecret.txt"]
+        {
+            assert_eq!(status_of(&service, path).await,
+                       (StatusCode::NOT_FOUND, Bytes::new()), "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn paths_are_percent_decoded() {
+        let root = root_with(&[("my file.txt", b"spaced"),
+                               ("café/menu.txt", b"menu"),
+                               ("a%2Fb.txt", b"literal")]);
+        let service = Arc::new(static_service(root.path()));
+        assert_eq!(get(&service, "/my%20file.txt").await, "spaced");
+        assert_eq!(get(&service, "/caf%C3%A9/menu.txt").await, "menu");
+        assert_eq!(get(&service, "/a%252Fb.txt").await, "literal");
+    }
+
+    #[tokio::test]
+    async fn encoded_separators_are_refused() {
+        let root = root_with(&[("a/b.txt", b"nested")]);
+        let service = Arc::new(static_service(root.path()));
+        for path in ["/a%2Fb.txt", "/a%2fb.txt"] {
+            assert_eq!(status_of(&service, path).await.0,
+                       StatusCode::FORBIDDEN, "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn paths_that_arent_utf8_are_bad_requests() {
+        let root = root_with(&[("index.html", b"")]);
+        let service = Arc::new(static_service(root.path()));
+        for path in ["/%FF.txt", "/%C3", "/%zz"] {
+            assert_eq!(status_of(&service, path).await.0,
+                       StatusCode::BAD_REQUEST, "{}", path);
+        }
+    }
+
+    // Serve the files in `root` with `options`.
+    fn service_with(root: &Path, options: StaticFileOptions) ->
+        Arc<DevProxService>
+    {
+        let mut service = static_service(root);
+        service.file_options(options);
+        Arc::new(service)
+    }
+
+    #[tokio::test]
+    async fn missing_files_get_the_not_found_page() {
+        let root = root_with(&[("404.html", b"not here")]);
+        let service = service_with(root.path(), StaticFileOptions {
+            not_found: Some(PathBuf::from("404.html")),
+            ..StaticFileOptions::default()
+        });
+        let (parts, body) = get_with(&service, "/missing.js", &[]).await;
+        assert_eq!(parts.status, StatusCode::NOT_FOUND);
+        assert_eq!(parts.headers["content-type"], "text/html; charset=utf-8");
+        assert_eq!(body, "not here");
+    }
+
+    #[tokio::test]
+    async fn a_missing_not_found_page_is_empty() {
+        let root = root_with(&[("index.html", b"")]);
+        let service = service_with(root.path(), StaticFileOptions {
+            not_found: Some(PathBuf::from("404.html")),
+            ..StaticFileOptions::default()
+        });
+        assert_eq!(status_of(&service, "/missing.js").await,
+                   (StatusCode::NOT_FOUND, Bytes::new()));
+    }
+
+    #[tokio::test]
+    async fn navigations_fall_back_before_the_not_found_page() {
+        let root = root_with(&[("index.html", b"shell"),
+                               ("404.html", b"not here")]);
+        let service = service_with(root.path(), StaticFileOptions {
+            not_found: Some(PathBuf::from("404.html")), spa: true,
+            ..StaticFileOptions::default()
+        });
+        let (parts, body) = get_with(
+            &service, "/users/42", &[("accept", "text/html")]).await;
+        assert_eq!((parts.status, body), (StatusCode::OK, "shell".into()));
+
+        let (parts, body) = get_with(
+            &service, "/missing.js", &[("accept", "*/*")]).await;
+        assert_eq!((parts.status, body),
+                   (StatusCode::NOT_FOUND, "not here".into()));
+    }
+
+    #[tokio::test]
+    async fn large_scripts_are_gzipped_for_clients_that_accept_it() {
+        let script = "console.log('hello, world');\n".repeat(1000);
+        let root = root_with(&[("app.js", script.as_bytes()),
+                               ("tiny.js", b"f();")]);
+        let service = Arc::new(static_service(root.path()));
+
+        let (parts, body) = get_with(&service, "/app.js", &[]).await;
+        assert!(!parts.headers.contains_key("content-encoding"));
+        assert_eq!(parts.headers["vary"], "accept-encoding");
+        assert_eq!(body, script);
+
+        let (parts, body) = get_with(
+            &service, "/app.js", &[("accept-encoding", "gzip")]).await;
+        assert_eq!(parts.headers["content-encoding"], "gzip");
+        assert_eq!(parts.headers["vary"], "accept-encoding");
+        assert!(!parts.headers.contains_key("content-length"));
+        assert!(body.len() < script.len());
+        let body = compression::decompress(Body::from(body),
+                                           compression::Encoding::Gzip);
+        assert_eq!(hyper::body::to_bytes(body).await.unwrap(), script);
+
+        let (parts, _) = get_with(
+            &service, "/tiny.js", &[("accept-encoding", "gzip")]).await;
+        assert!(!parts.headers.contains_key("content-encoding"));
+    }
+
+    #[tokio::test]
+    async fn listings_link_to_every_entry() {
+        let root = root_with(&[("build/app.js", b""),
+                               ("build/my page.html", b""),
+                               ("build/assets/logo.png", b"")]);
+        let service = service_with(root.path(), StaticFileOptions {
+            listing: true, ..StaticFileOptions::default()
+        });
+        let (_, body) = get_with(&service, "/build/", &[]).await;
+        let html = String::from_utf8(body.to_vec()).unwrap();
+        for link in ["href=\"assets/\"", "href=\"app.js\"",
+                     "href=\"my%20page.html\""]
+        {
+            assert!(html.contains(link), "{}: {}", link, html);
+        }
+        assert!(html.find("assets/").unwrap() < html.find("app.js").unwrap());
+    }
+
+    #[tokio::test]
+    async fn cache_control_applies_to_static_files_alone() {
+        let root = root_with(&[("app.3f9a.js", b""), ("index.html", b"")]);
+        let upstream = upstream(|_| Response::new(Body::from("api")));
+        let mut service = proxy_service(root.path(), "/api", upstream);
+        service.file_options(StaticFileOptions {
+            cache_rules: vec![cache_control::CacheRule {
+                pattern: "*.*.js".to_string(),
+                value: "max-age=31536000, immutable".to_string(),
+            }],
+            ..StaticFileOptions::default()
+        });
+        let service = Arc::new(service);
+
+        let (parts, _) = get_with(&service, "/app.3f9a.js", &[]).await;
+        assert_eq!(parts.headers["cache-control"],
+                   "max-age=31536000, immutable");
+        let (parts, _) = get_with(&service, "/", &[]).await;
+        assert_eq!(parts.headers["cache-control"], "no-cache");
+        let (parts, _) = get_with(&service, "/api/app.3f9a.js", &[]).await;
+        assert!(!parts.headers.contains_key("cache-control"));
+    }
+
+    #[tokio::test]
+    async fn precompressed_copies_are_served_to_clients_that_accept_them() {
+        let root = root_with(&[("bundle.js", b"plain"),
+                               ("bundle.js.gz", b"gzipped"),
+                               ("bundle.js.br", b"brotli"),
+                               ("other.js", b"other"),
+                               ("other.js.gz", b"other gzipped")]);
+        let service = Arc::new(static_service(root.path()));
+
+        let (plain, body) = get_with(&service, "/bundle.js", &[]).await;
+        assert_eq!(body, "plain");
+        assert!(!plain.headers.contains_key("content-encoding"));
+
+        let (parts, body) = get_with(
+            &service, "/bundle.js", &[("accept-encoding", "br, gzip")]).await;
+        assert_eq!(body, "brotli");
+        assert_eq!(parts.headers["content-encoding"], "br");
+        assert_eq!(parts.headers["vary"], "accept-encoding");
+        assert_eq!(parts.headers["content-type"],
+                   plain.headers["content-type"]);
+        assert_ne!(parts.headers["etag"], plain.headers["etag"]);
+
+        let (parts, body) = get_with(
+            &service, "/bundle.js", &[("accept-encoding", "gzip")]).await;
+        assert_eq!(body, "gzipped");
+        assert_eq!(parts.headers["content-encoding"], "gzip");
+
+        let (parts, body) = get_with(
+            &service, "/other.js", &[("accept-encoding", "br, gzip")]).await;
+        assert_eq!(body, "other gzipped");
+        assert_eq!(parts.headers["content-encoding"], "gzip");
+    }
+
+    #[tokio::test]
+    async fn proxied_responses_are_compressed_on_the_fly() {
+        let json = "{\"hello\": \"world\"}\n".repeat(100);
+        // An upstream answering with `json` as `content_type`, and any
+        // other `headers`.
+        let typed = |content_type: &'static str,
+                     headers: &'static [(&'static str, &'static str)]| {
+            let json = json.clone();
+            upstream(move |_| {
+                let mut response = Response::builder()
+                    .header("content-type", content_type);
+                for (name, value) in headers {
+                    response = response.header(*name, *value);
+                }
+                response.body(Body::from(json.clone())).unwrap()
+            })
+        };
+        let root = tempfile::tempdir().unwrap();
+        let mut service = static_service(root.path());
+        service.proxy(ProxyRoute::new("/api".to_string(), vec![
+            typed("application/json", &[])]));
+        service.proxy(ProxyRoute::new("/encoded".to_string(), vec![
+            typed("application/json", &[("content-encoding", "br")])]));
+        service.proxy(ProxyRoute::new("/image".to_string(), vec![
+            typed("image/png", &[])]));
+        let mut uncompressed = ProxyRoute::new("/raw".to_string(), vec![
+            typed("application/json", &[])]);
+        uncompressed.compression(false);
+        service.proxy(uncompressed);
+        let service = Arc::new(service);
+
+        let gzip = [("accept-encoding", "gzip")];
+        let (parts, body) = get_with(&service, "/api/data", &gzip).await;
+        assert_eq!(parts.headers["content-encoding"], "gzip");
+        assert_eq!(parts.headers["vary"], "accept-encoding");
+        assert!(!parts.headers.contains_key("content-length"));
+        let body = compression::decompress(Body::from(body),
+                                           compression::Encoding::Gzip);
+        assert_eq!(hyper::body::to_bytes(body).await.unwrap(), json);
+
+        let (parts, _) = get_with(&service, "/encoded/data", &gzip).await;
+        assert_eq!(parts.headers["content-encoding"], "br");
+        for path in ["/image/logo.png", "/raw/data"] {
+            let (parts, body) = get_with(&service, path, &gzip).await;
+            assert!(!parts.headers.contains_key("content-encoding"), "{}",
+                    path);
+            assert_eq!(body, json);
+        }
+    }
+
+    #[test]
+    fn prefixes_match_on_segment_boundaries() {
+        let route = ProxyRoute::new("/api".to_string(),
+                                    vec!["http://localhost".parse().unwrap()]);
+        for path in ["/api", "/api/", "/api/users"] {
+            assert!(route.matches(path), "{}", path);
+        }
+        for path in ["/apixyz", "/apiv2/users", "/ap", "/"] {
+            assert!(!route.matches(path), "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn similar_paths_are_not_proxied() {
+        let root = root_with(&[("apixyz", b"static")]);
+        let upstream = upstream(|_| Response::new(Body::from("proxied")));
+        let service = Arc::new(proxy_service(root.path(), "/api", upstream));
+        assert_eq!(get(&service, "/api").await, "proxied");
+        assert_eq!(get(&service, "/api/users").await, "proxied");
+        assert_eq!(get(&service, "/apixyz").await, "static");
+    }
+
+    #[tokio::test]
+    async fn a_cache_size_of_zero_disables_the_cache() {
+        let root = root_with(&[("app.js", b"one")]);
+        let config = |cache_size| Config {
+            root: vec![root.path().to_path_buf()], cache_size,
+            ..Config::default()
+        };
+        for cache_size in [None, Some(0)] {
+            let service = build_service(&config(cache_size)).unwrap();
+            assert!(service.cache.is_none(), "{:?}", cache_size);
+        }
+
+        let service = Arc::new(build_service(&config(Some(1024))).unwrap());
+        assert!(service.cache.is_some());
+        assert_eq!(get(&service, "/app.js").await, "one");
+    }
+
+    #[tokio::test]
+    async fn the_longest_matching_prefix_wins() {
+        let root = tempfile::tempdir().unwrap();
+        let mut service = static_service(root.path());
+        for (prefix, name) in [("/api", "api"), ("/api/auth/login", "login"),
+                               ("/api/auth", "auth")]
+        {
+            let upstream = upstream(move |_| Response::new(Body::from(name)));
+            service.proxy(
+                ProxyRoute::new(prefix.to_string(), vec![upstream]));
+        }
+        let service = Arc::new(service);
+        assert_eq!(get(&service, "/api/users").await, "api");
+        assert_eq!(get(&service, "/api/auth/logout").await, "auth");
+        assert_eq!(get(&service, "/api/auth/login").await, "login");
+        assert_eq!(get(&service, "/api/authors").await, "api");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn symlinks_are_followed_according_to_the_policy() {
+        use std::os::unix::fs::symlink;
+
+        let parent = root_with(&[("outside/secret.txt", b"secret"),
+                                 ("site/vendor/lib.js", b"lib")]);
+        let site = parent.path().join("site");
+        symlink(site.join("vendor"), site.join("modules")).unwrap();
+        symlink(parent.path().join("outside/secret.txt"),
+                site.join("secret.txt")).unwrap();
+        symlink(parent.path().join("outside"), site.join("outside")).unwrap();
+
+        let paths = ["/modules/lib.js", "/secret.txt", "/outside/secret.txt"];
+        let policies = [
+            (SymlinkPolicy::Follow, [true, true, true]),
+            (SymlinkPolicy::SameRoot, [true, false, false]),
+            (SymlinkPolicy::Deny, [false, false, false]),
+        ];
+        for (symlinks, allowed) in policies {
+            let service = service_with(&site, StaticFileOptions {
+                symlinks, ..StaticFileOptions::default()
+            });
+            for (path, allowed) in paths.iter().zip(allowed) {
+                let expected = match allowed {
+                    true => StatusCode::OK,
+                    false => StatusCode::FORBIDDEN,
+                };
+                assert_eq!(status_of(&service, path).await.0, expected,
+                           "{}", path);
+            }
+        }
+        assert!(SymlinkPolicy::default() == SymlinkPolicy::SameRoot);
+    }
+
+    #[tokio::test]
+    async fn charsets_are_declared_for_text_alone() {
+        let root = root_with(&[("legacy.html", b""), ("notes.txt", b""),
+                               ("page.htm", b""), ("logo.png", b"")]);
+        let service = service_with(root.path(), StaticFileOptions {
+            charset: Some("iso-8859-1".to_string()),
+            charsets: HashMap::from([
+                ("txt".to_string(), Some("utf-8".to_string())),
+                ("htm".to_string(), None),
+            ]),
+            ..StaticFileOptions::default()
+        });
+        let types = [
+            ("/legacy.html", "text/html; charset=iso-8859-1"),
+            ("/notes.txt", "text/plain; charset=utf-8"),
+            ("/page.htm", "text/html"),
+            ("/logo.png", "image/png"),
+        ];
+        for (path, expected) in types {
+            let (parts, _) = get_with(&service, path, &[]).await;
+            assert_eq!(parts.headers["content-type"], expected, "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn redirects_to_the_upstream_are_rewritten_under_the_route() {
+        let root = tempfile::tempdir().unwrap();
+        let redirecting = upstream(|request| {
+            let host = request.headers()["host"].to_str().unwrap();
+            let location = match request.uri().path() {
+                "/absolute" => format!("http://{}/login?next=1", host),
+                "/rooted" => "/login".to_string(),
+                "/relative" => "login".to_string(),
+                _ => "https://elsewhere.example/login".to_string(),
+            };
+            Response::builder().status(StatusCode::FOUND)
+                .header("location", location)
+                .body(Body::empty()).unwrap()
+        });
+        let service = Arc::new(
+            proxy_service(root.path(), "/api", redirecting));
+
+        let redirects = [
+            ("/api/absolute", "/api/login?next=1"),
+            ("/api/rooted", "/api/login"),
+            ("/api/relative", "login"),
+            ("/api/foreign", "https://elsewhere.example/login"),
+        ];
+        for (path, expected) in redirects {
+            let (parts, _) = get_with(&service, path, &[]).await;
+            assert_eq!(parts.status, StatusCode::FOUND);
+            assert_eq!(parts.headers["location"], expected, "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn mime_types_can_be_overridden() {
+        let root = root_with(&[("model.GLB", b"glTF"), ("clip.ts", b"G"),
+                               ("shader.wgsl", b"fn main() {}")]);
+        let content_type = |service: Arc<DevProxService>, path| async move {
+            let (parts, _) = get_with(&service, path, &[]).await;
+            parts.headers["content-type"].to_str().unwrap().to_string()
+        };
+
+        let mut service = static_service(root.path());
+        service.mime_type(".glb", "model/gltf-binary");
+        service.mime_type("TS", "video/mp2t");
+        let service = Arc::new(service);
+        assert_eq!(content_type(service.clone(), "/model.GLB").await,
+                   "model/gltf-binary");
+        assert_eq!(content_type(service, "/clip.ts").await, "video/mp2t");
+
+        let config: Config = toml::from_str(&format!(
+            "root = {:?}\n[mime_types]\nwgsl = \"text/wgsl\"\n",
+            root.path())).unwrap();
+        let service = Arc::new(build_service(&config).unwrap());
+        assert_eq!(content_type(service, "/shader.wgsl").await,
+                   "text/wgsl; charset=utf-8");
+    }
+
+    // Send a GET request for `path` with `headers` on `connection`, through
+    // `service`, to an upstream that answers with the headers it was sent.
+    async fn headers_upstream_saw(service: &Arc<DevProxService>, path: &str,
+                                  headers: &[(&str, &str)],
+                                  connection: Connection) -> String
+    {
+        let mut request = Request::get(path);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let response = service.respond(
+            request.body(Body::empty()).unwrap(), connection).await.unwrap();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn upstreams_are_told_where_requests_came_from() {
+        let root = tempfile::tempdir().unwrap();
+        let service = Arc::new(
+            proxy_service(root.path(), "/api", upstream(echo_headers)));
+        let connection = Connection {
+            peer: Some("192.0.2.7:51234".parse().unwrap()), secure: true,
+        };
+        let headers = headers_upstream_saw(&service, "/api/me", &[
+            ("host", "app.test:8080"),
+            ("x-forwarded-for", "10.0.0.1"),
+        ], connection).await;
+        assert!(headers.contains("x-forwarded-for: 10.0.0.1, 192.0.2.7\n"),
+                "{}", headers);
+        assert!(headers.contains("x-forwarded-proto: https\n"), "{}", headers);
+        assert!(headers.contains("x-forwarded-host: app.test:8080\n"),
+                "{}", headers);
+    }
+
+    #[tokio::test]
+    async fn directories_are_mounted_at_their_prefixes() {
+        let root = root_with(&[("dist/index.html", b"app"),
+                               ("docs/build/index.html", b"docs"),
+                               ("docs/build/guide.html", b"guide")]);
+        let mut service = static_service(&root.path().join("dist"));
+        service.mount(StaticRoute::new(
+            "/docs".to_string(), vec![root.path().join("docs/build")]));
+        let service = Arc::new(service);
+
+        assert_eq!(get(&service, "/").await, "app");
+        assert_eq!(get(&service, "/docs/").await, "docs");
+        assert_eq!(get(&service, "/docs/guide.html").await, "guide");
+        for path in ["/guide.html", "/docs/docs/guide.html", "/docsguide.html"]
+        {
+            assert_eq!(status_of(&service, path).await.0,
+                       StatusCode::NOT_FOUND, "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn slow_upstreams_time_out() {
+        // The upstream accepts connections, but never responds.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let hung = format!("http://{}", listener.local_addr().unwrap())
+            .parse().unwrap();
+        tokio::spawn(async move {
+            let mut connections = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                connections.push(stream);
+            }
+        });
+
+        let root = tempfile::tempdir().unwrap();
+        let mut route = ProxyRoute::new("/api".to_string(), vec![hung]);
+        route.timeout(Duration::from_millis(100));
+        let mut service = static_service(root.path());
+        service.proxy(route);
+        let service = Arc::new(service);
+
+        let start = Instant::now();
+        assert_eq!(status_of(&service, "/api/slow").await.0,
+                   StatusCode::GATEWAY_TIMEOUT);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn static_mounts_can_sit_inside_proxied_prefixes() {
+        let root = root_with(&[("public/app.js", b"asset"),
+                               ("public/api/app.js", b"not proxied")]);
+        let public = root.path().join("public");
+        let backend = upstream(|_| Response::new(Body::from("backend")));
+        let api = upstream(|_| Response::new(Body::from("api")));
+
+        // The more specific route wins, whichever was added first.
+        let mut first = static_service(root.path());
+        first.mount(StaticRoute::new("/assets".to_string(),
+                                     vec![public.clone()]));
+        first.proxy(ProxyRoute::new("/".to_string(), vec![backend.clone()]));
+        first.proxy(ProxyRoute::new("/assets/api".to_string(),
+                                    vec![api.clone()]));
+        let mut second = static_service(root.path());
+        second.proxy(ProxyRoute::new("/assets/api".to_string(), vec![api]));
+        second.proxy(ProxyRoute::new("/".to_string(), vec![backend]));
+        second.mount(StaticRoute::new("/assets".to_string(), vec![public]));
+
+        for service in [first, second] {
+            let service = Arc::new(service);
+            assert_eq!(get(&service, "/assets/app.js").await, "asset");
+            assert_eq!(get(&service, "/assets/api/app.js").await, "api");
+            assert_eq!(get(&service, "/users/42").await, "backend");
+            assert_eq!(status_of(&service, "/assets/missing.js").await.0,
+                       StatusCode::NOT_FOUND);
+        }
+    }
+
+    // Echo whatever is sent over the protocol that `request` upgrades to:
+    // messages for WebSockets, and bytes for anything else. Requests that
+    // don't upgrade are answered "plain".
+    fn echo_upgrade(mut request: Request<Body>) -> Response<Body> {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::{
+            WebSocketStream,
+            tungstenite::{handshake::derive_accept_key, protocol::Role},
+        };
+
+        let protocol = match upgrade_protocol(request.headers()) {
+            Some(protocol) => protocol.clone(),
+            None => return Response::new(Body::from("plain")),
+        };
+        let mut response = Response::builder()
+            .status(StatusCode::SWITCHING_PROTOCOLS)
+            .header("connection", "upgrade")
+            .header("upgrade", protocol.clone());
+        if let Some(key) = request.headers().get("sec-websocket-key") {
+            response = response.header(
+                "sec-websocket-accept", derive_accept_key(key.as_bytes()));
+        }
+
+        let upgrade = hyper::upgrade::on(&mut request);
+        tokio::spawn(async move {
+            let upgraded = upgrade.await.unwrap();
+            if protocol != "websocket" {
+                let (mut reader, mut writer) = tokio::io::split(upgraded);
+                let _ = tokio::io::copy(&mut reader, &mut writer).await;
+                return;
+            }
+
+            let mut socket = WebSocketStream::from_raw_socket(
+                upgraded, Role::Server, None).await;
+            while let Some(Ok(message)) = socket.next().await {
+                if message.is_close() || socket.send(message).await.is_err() {
+                    break;
+                }
+            }
+        });
+        response.body(Body::empty()).unwrap()
+    }
+
+    // Serve `service` on a port of its own, returning its address.
+    fn listen(service: DevProxService) -> SocketAddr {
+        let service = Arc::new(service);
+        let server = hyper::Server::bind(&"127.0.0.1:0".parse().unwrap())
+            .serve(make_service_fn(move |_| {
+                let service = service.clone();
+                async move {
+                    Ok::<_, Infallible>(hyper::service::service_fn(
+                        move |request| service.respond(
+                            request, Connection::default())))
+                }
+            }));
+        let address = server.local_addr();
+        tokio::spawn(server);
+        address
+    }
+
+    #[tokio::test]
+    async fn websockets_are_proxied() {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::Message;
+
+        let root = tempfile::tempdir().unwrap();
+        let address = listen(
+            proxy_service(root.path(), "/api", upstream(echo_upgrade)));
+        let url = format!("ws://{}/api/ws", address);
+        let (mut socket, _) = tokio_tungstenite::connect_async(url).await
+            .unwrap();
+        socket.send(Message::Text("hello".to_string())).await.unwrap();
+        assert_eq!(socket.next().await.unwrap().unwrap(),
+                   Message::Text("hello".to_string()));
+
+        // Requests that don't upgrade are proxied as usual.
+        let uri = format!("http://{}/api/plain", address).parse().unwrap();
+        let response = hyper::Client::new().get(uri).await.unwrap();
+        assert_eq!(hyper::body::to_bytes(response.into_body()).await.unwrap(),
+                   "plain");
+    }
+
+    #[tokio::test]
+    async fn index_files_are_tried_in_the_configured_order() {
+        let root = root_with(&[("index.html", b"html"),
+                               ("default.htm", b"htm"),
+                               ("docs/index.html", b"docs html"),
+                               ("docs/index.xhtml", b"docs xhtml")]);
+        let mut service = static_service(root.path());
+        service.file_options(StaticFileOptions {
+            index: Arc::from(["default.htm".to_string(),
+                              "index.html".to_string()]),
+            ..StaticFileOptions::default()
+        });
+        let mut docs = StaticRoute::new(
+            "/docs".to_string(), vec![root.path().join("docs")]);
+        docs.index(vec!["index.xhtml".to_string()]);
+        service.mount(docs);
+        let service = Arc::new(service);
+
+        assert_eq!(get(&service, "/").await, "htm");
+        assert_eq!(get(&service, "/docs/").await, "docs xhtml");
+    }
+
+    // Keeps the access log of every test in this process.
+    struct AccessLog(Mutex<Vec<String>>);
+
+    impl log::Log for AccessLog {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.target() == "access"
+        }
+
+        fn log(&self, record: &log::Record) {
+            if self.enabled(record.metadata()) {
+                self.0.lock().unwrap().push(record.args().to_string());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static ACCESS_LOG: AccessLog = AccessLog(Mutex::new(Vec::new()));
+
+    // The entry in the access log for `path`, if there is one.
+    fn access_log_entry(path: &str) -> Option<String> {
+        ACCESS_LOG.0.lock().unwrap().iter()
+            .find(|entry| entry.split(' ').nth(1) == Some(path))
+            .cloned()
+    }
+
+    #[tokio::test]
+    async fn every_request_is_logged() {
+        let _ = log::set_logger(&ACCESS_LOG);
+        log::set_max_level(log::LevelFilter::Info);
+        let root = root_with(&[("logged.txt", b"")]);
+        let upstream = upstream(|_| {
+            Response::builder().status(StatusCode::ACCEPTED)
+                .body(Body::empty()).unwrap()
+        });
+        let service = Arc::new(proxy_service(root.path(), "/api", upstream));
+        get(&service, "/logged.txt").await;
+        get(&service, "/api/logged").await;
+
+        let entry = access_log_entry("/logged.txt").unwrap();
+        assert!(entry.starts_with("GET /logged.txt 200 static "), "{}", entry);
+        let entry = access_log_entry("/api/logged").unwrap();
+        assert!(entry.starts_with("GET /api/logged 202 /api "), "{}", entry);
+    }
+
+    #[tokio::test]
+    async fn cross_origin_requests_are_allowed_from_the_origins() {
+        let root = root_with(&[("data.json", b"{}")]);
+        let mut service = static_service(root.path());
+        service.cors(Cors::new(vec!["http://localhost:3000".to_string()],
+                               true));
+        let service = Arc::new(service);
+
+        let request = Request::options("/data.json")
+            .header("origin", "http://localhost:3000")
+            .header("access-control-request-method", "PUT")
+            .header("access-control-request-headers", "content-type")
+            .body(Body::empty()).unwrap();
+        let (parts, _) = send(&service, request).await;
+        assert_eq!(parts.status, StatusCode::NO_CONTENT);
+        assert_eq!(parts.headers["access-control-allow-origin"],
+                   "http://localhost:3000");
+        assert!(parts.headers["access-control-allow-methods"].to_str()
+                .unwrap().contains("PUT"));
+        assert_eq!(parts.headers["access-control-allow-headers"],
+                   "content-type");
+        assert_eq!(parts.headers["access-control-allow-credentials"], "true");
+
+        let (parts, body) = get_with(
+            &service, "/data.json",
+            &[("origin", "http://localhost:3000")]).await;
+        assert_eq!(body, "{}");
+        assert_eq!(parts.headers["access-control-allow-origin"],
+                   "http://localhost:3000");
+
+        let (parts, _) = get_with(
+            &service, "/data.json", &[("origin", "http://evil.test")]).await;
+        assert!(!parts.headers.contains_key("access-control-allow-origin"));
+    }
+
+    #[test]
+    fn windows_paths_are_refused_everywhere() {
+        let paths = ["/C:/Windows/win.ini", "/c:", "/a/C:secret",
+                     "/\\\\server\\share\\file", "/dir\\file.txt",
+                     "/a/b:stream"];
+        for path in paths {
+            assert!(relative_path(path).is_err(), "{:?}", path);
+        }
+        assert_eq!(relative_path("/a/b/c.txt").unwrap(),
+                   ["a", "b", "c.txt"].iter().collect::<PathBuf>());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn relative_paths_use_the_platform_separator() {
+        let path = relative_path("/a/b/c.txt").unwrap();
+        assert_eq!(path.to_str(), Some("a\\b\\c.txt"));
+        assert!(path.is_relative());
+    }
+
+    // Split a multipart/byteranges body into the Content-Range and contents
+    // of each part.
+    fn byteranges(body: &str, boundary: &str) -> Vec<(String, String)> {
+        let closing = format!("\r\n--{}--\r\n", boundary);
+        let body = body.strip_suffix(&closing).unwrap();
+        body.split(&format!("--{}\r\n", boundary)).skip(1)
+            .map(|part| {
+                let part = part.strip_suffix("\r\n").unwrap_or(part);
+                let (headers, contents) = part.split_once("\r\n\r\n")
+                    .unwrap();
+                let range = headers.lines()
+                    .find_map(|line| line.strip_prefix("content-range: "))
+                    .unwrap();
+                (range.to_string(), contents.to_string())
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn multiple_ranges_are_served_as_parts() {
+        let contents: String = (0..2000)
+            .map(|i| (b'a' + (i % 26) as u8) as char)
+            .collect();
+        let root = root_with(&[("book.txt", contents.as_bytes())]);
+        let service = Arc::new(static_service(root.path()));
+
+        let (parts, body) = get_with(
+            &service, "/book.txt",
+            &[("range", "bytes=1000-1099, 0-99, 50-149")]).await;
+        assert_eq!(parts.status, StatusCode::PARTIAL_CONTENT);
+        let content_type = parts.headers["content-type"].to_str().unwrap();
+        let boundary = content_type
+            .strip_prefix("multipart/byteranges; boundary=").unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert_eq!(parts.headers["content-length"],
+                   body.len().to_string().as_str());
+        assert_eq!(byteranges(&body, boundary), [
+            ("bytes 0-149/2000".to_string(), contents[..150].to_string()),
+            ("bytes 1000-1099/2000".to_string(),
+             contents[1000..1100].to_string()),
+        ]);
+
+        let many = (0..=MAX_RANGES).map(|i| format!("{}-{}", i * 10, i * 10))
+            .collect::<Vec<String>>().join(",");
+        let (parts, body) = get_with(
+            &service, "/book.txt", &[("range", &format!("bytes={}", many))])
+            .await;
+        assert_eq!(parts.status, StatusCode::OK);
+        assert_eq!(body, contents);
+    }
+
+    #[tokio::test]
+    async fn requests_alternate_between_upstreams() {
+        let root = tempfile::tempdir().unwrap();
+        let upstreams = ["one", "two"].map(|name| upstream(
+            move |_| Response::new(Body::from(name))));
+        let mut service = static_service(root.path());
+        service.proxy(ProxyRoute::new("/api".to_string(), upstreams.to_vec()));
+        let service = Arc::new(service);
+
+        let mut answers = Vec::new();
+        for _ in 0..4 {
+            answers.push(get(&service, "/api/who").await);
+        }
+        assert_eq!(answers, ["one", "two", "one", "two"]);
+    }
+
+    #[tokio::test]
+    async fn prefixes_are_stripped_preserved_or_replaced() {
+        let root = tempfile::tempdir().unwrap();
+        let mut service = static_service(root.path());
+        service.proxy(ProxyRoute::new(
+            "/stripped".to_string(), vec![upstream(echo_target)]));
+        let mut preserved = ProxyRoute::new(
+            "/preserved".to_string(), vec![upstream(echo_target)]);
+        preserved.strip_prefix(false);
+        service.proxy(preserved);
+        let mut replaced = ProxyRoute::new(
+            "/api/v1".to_string(), vec![upstream(echo_target)]);
+        replaced.replace_prefix("/internal".to_string());
+        service.proxy(replaced);
+        let service = Arc::new(service);
+
+        assert_eq!(get(&service, "/stripped/users?page=2").await,
+                   "/users?page=2");
+        assert_eq!(get(&service, "/stripped").await, "/");
+        assert_eq!(get(&service, "/preserved/users").await,
+                   "/preserved/users");
+        assert_eq!(get(&service, "/api/v1/users").await, "/internal/users");
+    }
+
+    #[tokio::test]
+    async fn prefixes_can_be_preserved_in_the_config() {
+        let root = tempfile::tempdir().unwrap();
+        let upstream = upstream(echo_target);
+        for setting in ["strip_prefix = false", "preserve_prefix = true"] {
+            let config: Config = toml::from_str(&format!(
+                "root = {:?}\n[[route]]\nprefix = \"/api\"\n\
+                 upstream = \"{}\"\n{}\n", root.path(), upstream, setting))
+                .unwrap();
+            let service = Arc::new(build_service(&config).unwrap());
+            assert_eq!(get(&service, "/api/users").await, "/api/users",
+                       "{}", setting);
+        }
+    }
+
+    #[tokio::test]
+    async fn environment_variables_are_substituted_into_files() {
+        std::env::set_var("DEV_PROXY_TEST_SERVICE_API_URL", "/api/v2");
+        let config = "api = '%DEV_PROXY_TEST_SERVICE_API_URL%';\n\
+                      flag = '%DEV_PROXY_TEST_SERVICE_UNSET%';\n";
+        let root = root_with(&[("config.js", config.as_bytes()),
+                               ("app.js", config.as_bytes())]);
+        let service = service_with(root.path(), StaticFileOptions {
+            substitutions: Some(Substitutions::from_env(
+                vec!["config.js".to_string()], "DEV_PROXY_TEST_SERVICE_")),
+            ..StaticFileOptions::default()
+        });
+
+        let (parts, body) = get_with(&service, "/config.js", &[]).await;
+        let expected = "api = '/api/v2';\n\
+                        flag = '%DEV_PROXY_TEST_SERVICE_UNSET%';\n";
+        assert_eq!(body, expected);
+        assert_eq!(parts.headers["content-length"],
+                   expected.len().to_string().as_str());
+        assert_eq!(get(&service, "/app.js").await, config);
+    }
+
+    #[tokio::test]
+    async fn health_checks_dont_need_the_root() {
+        let service = Arc::new(static_service(Path::new("/nonexistent/root")));
+        assert_eq!(status_of(&service, "/healthz").await,
+                   (StatusCode::OK, Bytes::from("ok\n")));
+
+        let root = root_with(&[("healthz", b"file"), ("ready", b"file")]);
+        let mut service = static_service(root.path());
+        service.health_check(Some("/ready".to_string()));
+        let service = Arc::new(service);
+        assert_eq!(get(&service, "/ready").await, "ok\n");
+        assert_eq!(get(&service, "/healthz").await, "file");
+
+        let mut service = static_service(root.path());
+        service.health_check(None);
+        let service = Arc::new(service);
+        assert_eq!(get(&service, "/healthz").await, "file");
+    }
+
+    // Send a `method` request for `path` with `body`, returning the status.
+    async fn write_to(service: &Arc<DevProxService>, method: Method,
+                      path: &str, body: &'static str) -> StatusCode
+    {
+        let request = Request::builder().method(method).uri(path)
+            .body(Body::from(body)).unwrap();
+        send(service, request).await.0.status
+    }
+
+    #[tokio::test]
+    async fn files_are_only_written_when_allowed() {
+        let root = root_with(&[("index.html", b"home")]);
+        let service = Arc::new(static_service(root.path()));
+        for method in [Method::PUT, Method::DELETE] {
+            assert_eq!(write_to(&service, method, "/index.html", "").await,
+                       StatusCode::METHOD_NOT_ALLOWED);
+        }
+        assert_eq!(get(&service, "/index.html").await, "home");
+    }
+
+    #[tokio::test]
+    async fn files_are_uploaded_and_deleted() {
+        let parent = root_with(&[("site/index.html", b"home")]);
+        let root = parent.path().join("site");
+        let service = service_with(&root, StaticFileOptions {
+            allow_write: true, max_upload_size: 8,
+            ..StaticFileOptions::default()
+        });
+        let put = |path, body| write_to(&service, Method::PUT, path, body);
+
+        assert_eq!(put("/uploads/foo.png", "new").await, StatusCode::CREATED);
+        assert_eq!(std::fs::read(root.join("uploads/foo.png")).unwrap(),
+                   b"new");
+        assert_eq!(put("/uploads/foo.png", "newer").await,
+                   StatusCode::NO_CONTENT);
+        assert_eq!(get(&service, "/uploads/foo.png").await, "newer");
+
+        assert_eq!(put("/big.bin", "too large!").await,
+                   StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(!root.join("big.bin").exists());
+        assert_eq!(put("/../escaped.txt", "").await, StatusCode::FORBIDDEN);
+        assert!(!parent.path().join("escaped.txt").exists());
+        assert_eq!(put("/.env", "").await, StatusCode::NOT_FOUND);
+        assert_eq!(put("/uploads", "").await, StatusCode::CONFLICT);
+
+        assert_eq!(write_to(&service, Method::DELETE, "/uploads/foo.png", "")
+                   .await, StatusCode::NO_CONTENT);
+        assert!(!root.join("uploads/foo.png").exists());
+        assert_eq!(write_to(&service, Method::DELETE, "/uploads/foo.png", "")
+                   .await, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn queries_round_trip_exactly() {
+        let root = tempfile::tempdir().unwrap();
+        let service = Arc::new(
+            proxy_service(root.path(), "/api", upstream(echo_target)));
+        let queries = ["/api/users?limit=10", "/api/users?",
+                       "/api/users?q=%3Fwhat%3F&x=%26", "/api/users?a=1?b=2",
+                       "/api/users"];
+        for query in queries {
+            let expected = query.strip_prefix("/api").unwrap();
+            assert_eq!(get(&service, query).await, expected, "{}", query);
+        }
+    }
+
+    #[tokio::test]
+    async fn routes_add_headers_in_both_directions() {
+        let root = tempfile::tempdir().unwrap();
+        let upstream = upstream(|request| {
+            let token = request.headers()["x-internal-token"].clone();
+            Response::builder()
+                .header("cache-control", "no-store")
+                .header("x-token-seen", token)
+                .body(Body::empty()).unwrap()
+        });
+        let mut route = ProxyRoute::new("/api".to_string(), vec![upstream]);
+        route.add_request_header(HeaderName::from_static("x-internal-token"),
+                                 HeaderValue::from_static("secret"));
+        route.add_response_header(HeaderName::from_static("cache-control"),
+                                  HeaderValue::from_static("max-age=60"));
+        let mut service = static_service(root.path());
+        service.proxy(route);
+        let service = Arc::new(service);
+
+        let (parts, _) = get_with(
+            &service, "/api/data", &[("x-internal-token", "forged")]).await;
+        assert_eq!(parts.headers["x-token-seen"], "secret");
+        let cache_control: Vec<_> = parts.headers
+            .get_all("cache-control").iter().collect();
+        assert_eq!(cache_control, ["max-age=60"]);
+    }
+
+    #[tokio::test]
+    async fn delays_postpone_responses_without_blocking() {
+        let root = root_with(&[("app.js", b"app")]);
+        let upstream = upstream(|_| Response::new(Body::from("slow")));
+        let mut route = ProxyRoute::new("/api".to_string(), vec![upstream]);
+        route.delay(Duration::from_millis(300));
+        let mut service = static_service(root.path());
+        service.proxy(route);
+        let service = Arc::new(service);
+
+        let timed = |path| {
+            let service = service.clone();
+            async move {
+                let start = Instant::now();
+                get(&service, path).await;
+                start.elapsed()
+            }
+        };
+        let (delayed, other) = tokio::join!(timed("/api/data"),
+                                            timed("/app.js"));
+        assert!(delayed >= Duration::from_millis(300), "{:?}", delayed);
+        assert!(other < Duration::from_millis(300), "{:?}", other);
+    }
+
+    #[tokio::test]
+    async fn earlier_roots_shadow_later_ones() {
+        let root = root_with(&[("local/app.js", b"local app"),
+                               ("dist/app.js", b"dist app"),
+                               ("dist/vendor.js", b"dist vendor"),
+                               ("secret.txt", b"secret")]);
+        let service = Arc::new(DevProxService::new(vec![
+            root.path().join("local"), root.path().join("dist")]));
+        assert_eq!(get(&service, "/app.js").await, "local app");
+        assert_eq!(get(&service, "/vendor.js").await, "dist vendor");
+        assert_eq!(status_of(&service, "/missing.js").await.0,
+                   StatusCode::NOT_FOUND);
+        assert_eq!(status_of(&service, "/../secret.txt").await,
+                   (StatusCode::FORBIDDEN, Bytes::new()));
+    }
+
+    #[tokio::test]
+    async fn hosts_are_served_from_their_own_roots() {
+        let root = root_with(&[("default/index.html", b"default"),
+                               ("app1/index.html", b"app1"),
+                               ("app2/index.html", b"app2")]);
+        let mut service = static_service(&root.path().join("default"));
+        for name in ["app1", "app2"] {
+            service.virtual_host(&format!("{}.localhost", name), vec![
+                Route::Static(StaticRoute::new(
+                    "/".to_string(), vec![root.path().join(name)])),
+            ]);
+        }
+        let service = Arc::new(service);
+
+        let hosts = [("app1.localhost:8080", "app1"),
+                     ("APP2.localhost", "app2"), ("localhost", "default")];
+        for (host, expected) in hosts {
+            let (_, body) = get_with(&service, "/", &[("host", host)]).await;
+            assert_eq!(body, expected, "{}", host);
+        }
+    }
+
+    #[tokio::test]
+    async fn exact_routes_match_only_their_path() {
+        let root = root_with(&[("login/assets/app.js", b"asset"),
+                               ("login/index.html", b"disk")]);
+        let auth = upstream(|_| Response::new(Body::from("auth")));
+        let exact = |trailing_slash| {
+            let mut service = static_service(root.path());
+            service.proxy(ProxyRoute::new("/".to_string(), vec![
+                upstream(|_| Response::new(Body::from("prefix")))]));
+            let mut login = ProxyRoute::new(
+                "/login".to_string(), vec![auth.clone()]);
+            login.exact(trailing_slash);
+            service.proxy(login);
+            Arc::new(service)
+        };
+
+        let service = exact(false);
+        assert_eq!(get(&service, "/login").await, "auth");
+        assert_eq!(get(&service, "/login/").await, "prefix");
+        assert_eq!(get(&service, "/login/x").await, "prefix");
+
+        let service = exact(true);
+        assert_eq!(get(&service, "/login").await, "auth");
+        assert_eq!(get(&service, "/login/").await, "auth");
+        assert_eq!(get(&service, "/login/x").await, "prefix");
+
+        let mut service = static_service(root.path());
+        let mut login = ProxyRoute::new("/login".to_string(), vec![auth]);
+        login.exact(false);
+        service.proxy(login);
+        let service = Arc::new(service);
+        assert_eq!(get(&service, "/login/assets/app.js").await, "asset");
+    }
+
+    #[tokio::test]
+    async fn requests_are_counted_in_the_metrics() {
+        let root = root_with(&[("app.js", b"")]);
+        let upstream = upstream(|_| Response::new(Body::empty()));
+        let mut service = proxy_service(root.path(), "/api", upstream);
+        service.metrics();
+        let service = Arc::new(service);
+        for path in ["/app.js", "/missing.js", "/api/data"] {
+            get(&service, path).await;
+        }
+
+        let (parts, body) = get_with(&service, "/metrics", &[]).await;
+        assert_eq!(parts.status, StatusCode::OK);
+        let text = String::from_utf8(body.to_vec()).unwrap();
+        for line in [
+            "dev_proxy_requests_total{class=\"2xx\"} 2",
+            "dev_proxy_requests_total{class=\"4xx\"} 1",
+            "dev_proxy_route_requests_total{route=\"/api\"} 1",
+            "dev_proxy_route_requests_total{route=\"static\"} 2",
+            "dev_proxy_request_duration_seconds_count 3",
+        ] {
+            assert!(text.lines().any(|text| text == line), "{}", line);
+        }
+    }
+
+    #[tokio::test]
+    async fn served_files_are_cached() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("app.js"), "one").unwrap();
+        let roots = [root.path().to_path_buf()];
+        let mut service = static_service(root.path());
+        service.cache(FileCache::watch(1024, &roots).unwrap());
+        let service = Arc::new(service);
+
+        assert_eq!(get(&service, "/app.js").await, "one");
+        let (request, _) = Request::get("/app.js").body(()).unwrap()
+            .into_parts();
+        let key = file_cache::Key::new(&roots, &request);
+        let (_, contents, _) = service.cache.as_ref().unwrap().get(&key)
+            .unwrap();
+        assert_eq!(contents, "one");
+    }
+
+    #[tokio::test]
+    async fn cached_files_modified_since_are_read_again() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().join("app.js");
+        std::fs::write(&path, "two").unwrap();
+        let roots = [root.path().to_path_buf()];
+        let cache = FileCache::watch(1024, &roots).unwrap();
+
+        // As if the file changed without the watcher noticing.
+        let (request, _) = Request::get("/app.js").body(()).unwrap()
+            .into_parts();
+        let source = file_cache::Source {
+            path, modified: SystemTime::UNIX_EPOCH,
+        };
+        cache.insert(file_cache::Key::new(&roots, &request),
+                     cache.generation(), HeaderMap::new(), Bytes::from("one"),
+                     source);
+        let mut service = static_service(root.path());
+        service.cache(cache);
+        assert_eq!(get(&Arc::new(service), "/app.js").await, "two");
+    }
+
+    #[tokio::test]
+    async fn changing_a_file_drops_it_from_the_cache() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().join("app.js");
+        std::fs::write(&path, "one").unwrap();
+        let cache = FileCache::watch(1024, &[root.path().to_path_buf()])
+            .unwrap();
+        let mut service = static_service(root.path());
+        service.cache(cache);
+        let service = Arc::new(service);
+
+        assert_eq!(get(&service, "/app.js").await, "one");
+        std::fs::write(&path, "two").unwrap();
+        for _ in 0..100 {
+            if get(&service, "/app.js").await == "two" {
+                return;
+            }
+            time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("the cached file was never dropped");
+    }
+
+    #[tokio::test]
+    async fn files_are_not_cached_by_default() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().join("app.js");
+        std::fs::write(&path, "one").unwrap();
+        let service = Arc::new(static_service(root.path()));
+
+        assert_eq!(get(&service, "/app.js").await, "one");
+        std::fs::write(&path, "two").unwrap();
+        assert_eq!(get(&service, "/app.js").await, "two");
+    }
+
+    #[tokio::test]
+    async fn the_first_matching_rewrite_is_applied() {
+        let root = tempfile::tempdir().unwrap();
+        let rule = |pattern, replacement: &str| RewriteRule {
+            pattern: Regex::new(pattern).unwrap(),
+            replacement: replacement.to_string(),
+        };
+        let mut route = ProxyRoute::new(
+            "/old-api".to_string(), vec![upstream(echo_target)]);
+        route.rewrite(rule("^/old-api/v1/(.*)$", "/v2/$1"));
+        route.rewrite(rule("^/old-api/(.*)$", "/legacy/$1"));
+        let mut broken = ProxyRoute::new(
+            "/broken".to_string(), vec![upstream(echo_target)]);
+        broken.rewrite(rule("^/broken/(.*)$", "/not a path/$1"));
+        let mut service = static_service(root.path());
+        service.proxy(route);
+        service.proxy(broken);
+        let service = Arc::new(service);
+
+        assert_eq!(get(&service, "/old-api/v1/users?page=2").await,
+                   "/v2/users?page=2");
+        assert_eq!(get(&service, "/old-api/users").await, "/legacy/users");
+        assert_eq!(status_of(&service, "/broken/users").await.0,
+                   StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn host_overrides_cant_be_clobbered() {
+        let root = tempfile::tempdir().unwrap();
+        let mut route = ProxyRoute::new(
+            "/api".to_string(), vec![upstream(echo_headers)]);
+        route.host(HostPolicy::Override(
+            HeaderValue::from_static("staging.internal.example.com")));
+        route.add_request_header(HeaderName::from_static("host"),
+                                 HeaderValue::from_static("clobbered"));
+        let mut service = static_service(root.path());
+        service.proxy(route);
+        let service = Arc::new(service);
+
+        let headers = headers_upstream_saw(
+            &service, "/api/me", &[("host", "browser.test")],
+            Connection::default()).await;
+        let hosts: Vec<&str> = headers.lines()
+            .filter(|line| line.starts_with("host: ")).collect();
+        assert_eq!(hosts, ["host: staging.internal.example.com"]);
+    }
+
+    // Serve the files in a new directory with live reload, returning the
+    // directory to change them in.
+    fn live_reload_service() -> (tempfile::TempDir, DevProxService) {
+        let root = tempfile::tempdir().unwrap();
+        let mut service = static_service(root.path());
+        service.live_reload(
+            LiveReload::watch(&[root.path().to_path_buf()]).unwrap());
+        (root, service)
+    }
+
+    #[tokio::test]
+    async fn the_live_reload_script_is_injected_into_html() {
+        let (root, service) = live_reload_service();
+        std::fs::write(root.path().join("index.html"),
+                       "<html><body>hello</body></html>").unwrap();
+        std::fs::write(root.path().join("app.js"), "hello").unwrap();
+        let service = Arc::new(service);
+
+        let request = Request::get("/").body(Body::empty()).unwrap();
+        let (parts, body) = send(&service, request).await;
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains(live_reload::SCRIPT));
+        assert!(body.ends_with("</body></html>"));
+        assert_eq!(parts.headers["content-length"],
+                   body.len().to_string().as_str());
+
+        assert_eq!(get(&service, "/app.js").await, "hello");
+    }
+
+    // Connect to the live reload socket of `service`, and wait for the
+    // message it sends after `change` is made to its files.
+    async fn live_reload_message(change: impl FnOnce(&Path)) -> String {
+        use tokio_tungstenite::tungstenite::Message;
+
+        let (root, service) = live_reload_service();
+        let address = listen(service);
+        let url = format!("ws://{}{}", address, live_reload::SOCKET_PATH);
+        let (mut socket, _) = tokio_tungstenite::connect_async(url).await
+            .unwrap();
+
+        change(root.path());
+        let message = time::timeout(Duration::from_secs(5), socket.next())
+            .await.expect("no change was broadcast");
+        match message {
+            Some(Ok(Message::Text(text))) => text,
+            message => panic!("unexpected message: {:?}", message),
+        }
+    }
+
+    #[tokio::test]
+    async fn touching_a_file_broadcasts_a_reload() {
+        let message = live_reload_message(|root| {
+            std::fs::write(root.join("index.html"), "<p>new</p>").unwrap();
+        }).await;
+        assert_eq!(message, "reload");
+    }
+
+    #[tokio::test]
+    async fn changing_a_stylesheet_broadcasts_css() {
+        let message = live_reload_message(|root| {
+            std::fs::write(root.join("style.css"), "p {}").unwrap();
+        }).await;
+        assert_eq!(message, "css");
+    }
+
+    #[tokio::test]
+    async fn forwarding_headers_can_be_standard_or_omitted() {
+        let root = tempfile::tempdir().unwrap();
+        let mut service = static_service(root.path());
+        for (prefix, style) in [("/standard", ForwardedStyle::Forwarded),
+                                ("/omitted", ForwardedStyle::None)]
+        {
+            let mut route = ProxyRoute::new(
+                prefix.to_string(), vec![upstream(echo_headers)]);
+            route.forwarded(style);
+            service.proxy(route);
+        }
+        let service = Arc::new(service);
+        let connection = Connection {
+            peer: Some("[2001:db8::7]:51234".parse().unwrap()),
+            secure: false,
+        };
+        let request_headers = [("host", "app.test"),
+                               ("forwarded", "for=192.0.2.1")];
+
+        let headers = headers_upstream_saw(
+            &service, "/standard/me", &request_headers, connection).await;
+        assert!(headers.contains(
+            "forwarded: for=192.0.2.1, for=\"[2001:db8::7]\";proto=http;\
+             host=\"app.test\"\n"), "{}", headers);
+        assert!(!headers.contains("x-forwarded"), "{}", headers);
+
+        let headers = headers_upstream_saw(
+            &service, "/omitted/me", &request_headers, connection).await;
+        assert!(headers.contains("forwarded: for=192.0.2.1\n"), "{}", headers);
+        assert!(!headers.contains("x-forwarded"), "{}", headers);
+        assert!(!headers.contains("2001:db8::7"), "{}", headers);
+    }
+
+    // Send a `method` request through a route to an upstream that drops its
+    // first connection without responding, as if it were restarting, and
+    // answers "ok" on every one after that. It's retried `retries` times,
+    // after `backoff`, and each attempt times out after 200 milliseconds.
+    async fn send_to_flaky(method: Method, retries: u32, backoff: Duration) ->
+        StatusCode
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let flaky = format!("http://{}", listener.local_addr().unwrap())
+            .parse().unwrap();
+        tokio::spawn(async move {
+            drop(listener.accept().await.unwrap());
+            while let Ok((stream, _)) = listener.accept().await {
+                let service = hyper::service::service_fn(|_| ready(
+                    Ok::<_, Infallible>(Response::new(Body::from("ok")))));
+                tokio::spawn(hyper::server::conn::Http::new()
+                             .serve_connection(stream, service));
+            }
+        });
+
+        let root = tempfile::tempdir().unwrap();
+        let mut route = ProxyRoute::new("/api".to_string(), vec![flaky]);
+        route.retries(retries, backoff);
+        route.timeout(Duration::from_millis(200));
+        let mut service = static_service(root.path());
+        service.proxy(route);
+        let request = Request::builder().method(method).uri("/api/data")
+            .body(Body::empty()).unwrap();
+        send(&Arc::new(service), request).await.0.status
+    }
+
+    #[tokio::test]
+    async fn idempotent_requests_are_retried() {
+        let backoff = Duration::from_millis(10);
+        assert_eq!(send_to_flaky(Method::GET, 1, backoff).await,
+                   StatusCode::OK);
+        assert_eq!(send_to_flaky(Method::HEAD, 1, backoff).await,
+                   StatusCode::OK);
+        assert_eq!(send_to_flaky(Method::GET, 0, backoff).await,
+                   StatusCode::BAD_GATEWAY);
+        assert_eq!(send_to_flaky(Method::POST, 1, backoff).await,
+                   StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn each_attempt_gets_the_whole_timeout() {
+        // Waiting to retry takes longer than the timeout, on its own.
+        assert_eq!(send_to_flaky(Method::GET, 1, Duration::from_millis(300))
+                   .await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn requests_without_credentials_are_challenged() {
+        use base64::{Engine, engine::general_purpose::STANDARD};
+
+        let root = root_with(&[("index.html", b"home")]);
+        let upstream = upstream(|_| Response::new(Body::from("api")));
+        let mut service = proxy_service(root.path(), "/api", upstream);
+        service.basic_auth(
+            BasicAuth::new(vec!["alice:secret".to_string()]).unwrap());
+        let service = Arc::new(service);
+
+        let valid = format!("Basic {}", STANDARD.encode("alice:secret"));
+        let invalid = format!("Basic {}", STANDARD.encode("alice:guess"));
+        for path in ["/", "/api/data"] {
+            let (parts, _) = get_with(&service, path, &[]).await;
+            assert_eq!(parts.status, StatusCode::UNAUTHORIZED, "{}", path);
+            assert!(parts.headers.contains_key("www-authenticate"));
+            let (parts, _) = get_with(
+                &service, path, &[("authorization", &invalid)]).await;
+            assert_eq!(parts.status, StatusCode::UNAUTHORIZED, "{}", path);
+        }
+        let (_, body) = get_with(
+            &service, "/", &[("authorization", &valid)]).await;
+        assert_eq!(body, "home");
+        let (_, body) = get_with(
+            &service, "/api/data", &[("authorization", &valid)]).await;
+        assert_eq!(body, "api");
+    }
+
+    #[tokio::test]
+    async fn unmatched_paths_fall_through_to_the_catch_all() {
+        let root = root_with(&[("app.js", b"static")]);
+        let backend = upstream(|_| Response::new(Body::from("backend")));
+        let mut service = static_service(root.path());
+        service.catch_all(ProxyRoute::new("/".to_string(), vec![backend]));
+        let service = Arc::new(service);
+        assert_eq!(get(&service, "/app.js").await, "static");
+        assert_eq!(get(&service, "/users/42").await, "backend");
+    }
+
+    #[tokio::test]
+    async fn the_catch_all_is_configured_at_the_top_level() {
+        let root = root_with(&[("app.js", b"static")]);
+        let backend = upstream(echo_target);
+        let config: Config = toml::from_str(&format!(
+            "root = {:?}\n[catch_all]\nupstream = \"{}\"\n",
+            root.path(), backend)).unwrap();
+        let service = Arc::new(build_service(&config).unwrap());
+        assert_eq!(get(&service, "/app.js").await, "static");
+        assert_eq!(get(&service, "/users/42?tab=1").await, "/users/42?tab=1");
+    }
+
+    #[tokio::test]
+    async fn websocket_messages_and_closes_pass_through() {
+        use futures_util::SinkExt;
+        use tokio_tungstenite::tungstenite::{self, Message};
+
+        let root = tempfile::tempdir().unwrap();
+        let mut service = proxy_service(
+            root.path(), "/api", upstream(echo_upgrade));
+        service.proxy(ProxyRoute::new("/refused".to_string(), vec![
+            upstream(|_| {
+                Response::builder().status(StatusCode::FORBIDDEN)
+                    .body(Body::from("no sockets")).unwrap()
+            })]));
+        let address = listen(service);
+
+        let url = format!("ws://{}/api/ws", address);
+        let (mut socket, _) = tokio_tungstenite::connect_async(url).await
+            .unwrap();
+        let messages = [Message::Text("hello".to_string()),
+                        Message::Binary(vec![0, 1, 2, 255]),
+                        Message::Text("again".to_string())];
+        for message in messages.clone() {
+            socket.send(message).await.unwrap();
+        }
+        for message in messages {
+            assert_eq!(socket.next().await.unwrap().unwrap(), message);
+        }
+
+        // Closing one side closes the other, which closes the tunnel.
+        socket.close(None).await.unwrap();
+        let closed = time::timeout(Duration::from_secs(5), async {
+            while let Some(Ok(_)) = socket.next().await {}
+        }).await;
+        assert!(closed.is_ok(), "the tunnel was never closed");
+
+        let url = format!("ws://{}/refused/ws", address);
+        match tokio_tungstenite::connect_async(url).await {
+            Err(tungstenite::Error::Http(response)) => {
+                assert_eq!(response.status(), StatusCode::FORBIDDEN);
+                assert_eq!(response.body().as_deref(),
+                           Some(&b"no sockets"[..]));
+            },
+            result => panic!("unexpected result: {:?}", result.map(|_| ())),
+        }
+    }
+
+    #[tokio::test]
+    async fn any_protocol_can_be_upgraded_to() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let root = tempfile::tempdir().unwrap();
+        let mut service = proxy_service(
+            root.path(), "/api", upstream(echo_upgrade));
+        service.proxy(ProxyRoute::new("/declined".to_string(), vec![
+            upstream(|_| {
+                Response::builder().header("x-upgrade", "declined")
+                    .body(Body::from("still http")).unwrap()
+            })]));
+        let address = listen(service);
+        let upgrade = |path: &str, protocol: &'static str| {
+            let uri: Uri = format!("http://{}{}", address, path)
+                .parse().unwrap();
+            hyper::Client::new().request(Request::get(uri)
+                .header("connection", "upgrade")
+                .header("upgrade", protocol)
+                .body(Body::empty()).unwrap())
+        };
+
+        for protocol in ["custom-protocol/1", "h2c"] {
+            let response = upgrade("/api/tunnel", protocol).await.unwrap();
+            assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+            assert_eq!(response.headers()["upgrade"], protocol);
+            let mut tunnel = hyper::upgrade::on(response).await.unwrap();
+            tunnel.write_all(b"ping").await.unwrap();
+            let mut echoed = [0; 4];
+            tunnel.read_exact(&mut echoed).await.unwrap();
+            assert_eq!(&echoed, b"ping");
+        }
+
+        let response = upgrade("/declined/tunnel", "custom-protocol/1").await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["x-upgrade"], "declined");
+        assert_eq!(hyper::body::to_bytes(response.into_body()).await.unwrap(),
+                   "still http");
+    }
+
+    #[tokio::test]
+    async fn the_host_sent_upstream_follows_the_policy() {
+        let root = tempfile::tempdir().unwrap();
+        let upstream = upstream(echo_headers);
+        let authority = upstream.authority().unwrap().to_string();
+        let policies = [
+            (HostPolicy::Upstream, authority.as_str()),
+            (HostPolicy::Preserve, "browser.test"),
+            (HostPolicy::Override(HeaderValue::from_static(
+                "staging.internal.example.com")),
+             "staging.internal.example.com"),
+        ];
+        for (policy, expected) in policies {
+            let mut route = ProxyRoute::new(
+                "/api".to_string(), vec![upstream.clone()]);
+            route.host(policy);
+            let mut service = static_service(root.path());
+            service.proxy(route);
+            let headers = headers_upstream_saw(
+                &Arc::new(service), "/api/me", &[("host", "browser.test")],
+                Connection::default()).await;
+            let hosts: Vec<&str> = headers.lines()
+                .filter(|line| line.starts_with("host: ")).collect();
+            assert_eq!(hosts, [format!("host: {}", expected)]);
+        }
+    }
+
+    #[tokio::test]
+    async fn slow_chunked_responses_are_streamed() {
+        use futures_util::StreamExt;
+
+        let root = tempfile::tempdir().unwrap();
+        let slow = upstream(|_| {
+            let chunks = stream::iter(0..3).then(|index| async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                Ok::<_, std::io::Error>(format!("chunk {}\n", index))
+            });
+            Response::new(Body::wrap_stream(chunks))
+        });
+        let address = listen(proxy_service(root.path(), "/events", slow));
+        let uri = format!("http://{}/events/stream", address).parse().unwrap();
+        let response = hyper::Client::new().get(uri).await.unwrap();
+        assert!(response.headers().get("content-length").is_none());
+        assert_eq!(response.headers()["transfer-encoding"], "chunked");
+
+        // Each chunk arrives as the upstream sends it, not all at the end.
+        let mut body = response.into_body();
+        let mut arrivals = Vec::new();
+        let mut received = Vec::new();
+        while let Some(chunk) = body.next().await {
+            arrivals.push(Instant::now());
+            received.extend_from_slice(&chunk.unwrap());
+        }
+        assert_eq!(received, b"chunk 0\nchunk 1\nchunk 2\n");
+        assert_eq!(arrivals.len(), 3);
+        for pair in arrivals.windows(2) {
+            assert!(pair[1] - pair[0] >= Duration::from_millis(50));
+        }
+    }
+
+    #[tokio::test]
+    async fn faults_are_injected_at_the_configured_rate() {
+        let root = tempfile::tempdir().unwrap();
+        let faulty = |rate, seed| {
+            let mut route = ProxyRoute::new(
+                "/api".to_string(),
+                vec![upstream(|_| Response::new(Body::from("ok")))]);
+            route.inject_faults(FaultInjection::new(
+                rate, StatusCode::SERVICE_UNAVAILABLE, seed));
+            let mut service = static_service(root.path());
+            service.proxy(route);
+            Arc::new(service)
+        };
+
+        let always = faulty(1.0, None);
+        let never = faulty(0.0, None);
+        for _ in 0..20 {
+            assert_eq!(status_of(&always, "/api/me").await.0,
+                       StatusCode::SERVICE_UNAVAILABLE);
+            assert_eq!(status_of(&never, "/api/me").await,
+                       (StatusCode::OK, Bytes::from("ok")));
+        }
+
+        // The same seed fails the same requests.
+        let mut runs = Vec::new();
+        for _ in 0..2 {
+            let service = faulty(0.5, Some(7));
+            let mut statuses = Vec::new();
+            for _ in 0..20 {
+                statuses.push(status_of(&service, "/api/me").await.0);
+            }
+            runs.push(statuses);
+        }
+        assert_eq!(runs[0], runs[1]);
+        assert!(runs[0].contains(&StatusCode::OK));
+        assert!(runs[0].contains(&StatusCode::SERVICE_UNAVAILABLE));
+    }
 }
 
 ///////////////////////////////////////////////////////////////////////////////
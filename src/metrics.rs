@@ -0,0 +1,159 @@
+///////////////////////////////////////////////////////////////////////////////
+// NAME:            metrics.rs
+//
+// AUTHOR:          Ethan D. Twardy <ethan.twardy@gmail.com>
+//
+// DESCRIPTION:     Request metrics, in the Prometheus text format.
+//
+// CREATED:         10/15/2026
+//
+// LAST EDITED:     10/15/2026
+////
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use hyper::{Body, Response, StatusCode};
+
+pub const METRICS_PATH: &str = "/metrics";
+
+// The upper bounds of the buckets of the duration histogram, in seconds.
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+// Escape a label value, as the text format requires.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+// Counters shared by every connection. Each is only ever incremented, so
+// they're updated independently, without a lock.
+#[derive(Default)]
+pub struct Metrics {
+    // Requests by the class of their status (1xx through 5xx), and those that
+    // failed without a response.
+    classes: [AtomicU64; 5],
+    errors: AtomicU64,
+    // Requests by the route that handled them.
+    routes: Mutex<BTreeMap<String, u64>>,
+    // Requests by the first bucket their duration fits in, past the last of
+    // which is +Inf, and the total of their durations.
+    buckets: [AtomicU64; BUCKETS.len() + 1],
+    total_micros: AtomicU64,
+}
+
+impl Metrics {
+    // Count a request handled by `route`, whose response had `status`, or
+    // None if it failed.
+    pub fn record(&self, route: &str, status: Option<StatusCode>,
+                  elapsed: Duration)
+    {
+        let counter = match status.map(|status| status.as_u16() / 100) {
+            Some(class @ 1..=5) => &self.classes[class as usize - 1],
+            _ => &self.errors,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        *self.routes.lock().unwrap().entry(route.to_string()).or_default()
+            += 1;
+
+        let seconds = elapsed.as_secs_f64();
+        let bucket = BUCKETS.iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(BUCKETS.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.total_micros.fetch_add(
+            elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        let name = "dev_proxy_requests_total";
+        let _ = writeln!(text, "# HELP {} Requests, by class of status.",
+                         name);
+        let _ = writeln!(text, "# TYPE {} counter", name);
+        for (class, counter) in self.classes.iter().enumerate() {
+            let _ = writeln!(text, "{}{{class=\"{}xx\"}} {}", name, class + 1,
+                             counter.load(Ordering::Relaxed));
+        }
+        let _ = writeln!(text, "{}{{class=\"error\"}} {}", name,
+                         self.errors.load(Ordering::Relaxed));
+
+        let name = "dev_proxy_route_requests_total";
+        let _ = writeln!(text, "# HELP {} Requests, by route.", name);
+        let _ = writeln!(text, "# TYPE {} counter", name);
+        for (route, count) in self.routes.lock().unwrap().iter() {
+            let _ = writeln!(text, "{}{{route=\"{}\"}} {}", name,
+                             escape(route), count);
+        }
+
+        // Buckets of the text format are cumulative.
+        let name = "dev_proxy_request_duration_seconds";
+        let _ = writeln!(text, "# HELP {} Time taken to respond to requests.",
+                         name);
+        let _ = writeln!(text, "# TYPE {} histogram", name);
+        let mut count = 0;
+        for (index, bucket) in self.buckets.iter().enumerate() {
+            count += bucket.load(Ordering::Relaxed);
+            let bound = BUCKETS.get(index)
+                .map_or_else(|| "+Inf".to_string(), f64::to_string);
+            let _ = writeln!(text, "{}_bucket{{le=\"{}\"}} {}", name, bound,
+                             count);
+        }
+        let seconds = self.total_micros.load(Ordering::Relaxed) as f64
+            / 1_000_000.0;
+        let _ = writeln!(text, "{}_sum {}", name, seconds);
+        let _ = writeln!(text, "{}_count {}", name, count);
+        text
+    }
+
+    pub fn response(&self) -> Response<Body> {
+        Response::builder()
+            .header("content-type", "text/plain; version=0.0.4")
+            .header("cache-control", "no-store")
+            .body(Body::from(self.render()))
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_are_counted_by_class_route_and_duration() {
+        let metrics = Metrics::default();
+        metrics.record("static", Some(StatusCode::OK),
+                       Duration::from_millis(1));
+        metrics.record("/api", Some(StatusCode::BAD_GATEWAY),
+                       Duration::from_millis(30));
+        metrics.record("/api", None, Duration::from_secs(20));
+
+        let text = metrics.render();
+        for line in [
+            "dev_proxy_requests_total{class=\"2xx\"} 1",
+            "dev_proxy_requests_total{class=\"5xx\"} 1",
+            "dev_proxy_requests_total{class=\"error\"} 1",
+            "dev_proxy_route_requests_total{route=\"/api\"} 2",
+            "dev_proxy_route_requests_total{route=\"static\"} 1",
+            "dev_proxy_request_duration_seconds_bucket{le=\"0.005\"} 1",
+            "dev_proxy_request_duration_seconds_bucket{le=\"0.05\"} 2",
+            "dev_proxy_request_duration_seconds_bucket{le=\"10\"} 2",
+            "dev_proxy_request_duration_seconds_bucket{le=\"+Inf\"} 3",
+            "dev_proxy_request_duration_seconds_count 3",
+        ] {
+            assert!(text.lines().any(|text| text == line), "{}", line);
+        }
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
@@ -0,0 +1,184 @@
+///////////////////////////////////////////////////////////////////////////////
+// NAME:            reload.rs
+//
+// AUTHOR:          Ethan D. Twardy <ethan.twardy@gmail.com>
+//
+// DESCRIPTION:     Reloading the configuration without dropping connections.
+//
+// CREATED:         10/15/2026
+//
+// LAST EDITED:     10/15/2026
+////
+
+use core::task::{Context, Poll};
+
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+
+use clap::Parser;
+use hyper::{Body, Request, Response, service::Service};
+
+use crate::cli::Args;
+use crate::config::ConfigError;
+use crate::{
+    build_service, load_config, Connection, DevProxService, ProxyError,
+    ServiceFuture,
+};
+
+// Read the configuration again, as at startup.
+fn rebuild() -> Result<DevProxService, ConfigError> {
+    let args = Args::parse();
+    let mut config = load_config(&args)?;
+    args.apply(&mut config);
+    build_service(&config)
+}
+
+// Serves each request with whichever service is current when it arrives.
+// Every connection shares the current service, so a reload takes effect on
+// the next request, even on a connection that's already open. Requests take
+// a reference to the current service, rather than a copy of it.
+#[derive(Clone)]
+pub struct ReloadableService {
+    current: Arc<RwLock<Arc<DevProxService>>>,
+    // The connection this service handles requests from.
+    connection: Connection,
+}
+
+impl ReloadableService {
+    pub fn new(service: DevProxService) -> Self {
+        Self {
+            current: Arc::new(RwLock::new(Arc::new(service))),
+            connection: Connection::default(),
+        }
+    }
+
+    // Set the address of the client on the connection this service handles.
+    pub fn peer(&mut self, peer: SocketAddr) {
+        self.connection.peer = Some(peer);
+    }
+
+    // Mark the connection this service handles as being over TLS.
+    pub fn secure(&mut self) {
+        self.connection.secure = true;
+    }
+
+    // Replace the current service with `service`. Requests that have already
+    // been dispatched finish with the old one.
+    pub fn replace(&self, mut service: DevProxService) {
+        let mut current = self.current.write().unwrap();
+        // Keep counting where the old service left off.
+        if service.metrics.is_some() && current.metrics.is_some() {
+            service.metrics = current.metrics.clone();
+        }
+        *current = Arc::new(service);
+    }
+
+    // Replace the current service with one built from the configuration as
+    // it is now. If the configuration is broken, the old one is kept.
+    pub fn reload(&self) {
+        match rebuild() {
+            Ok(service) => {
+                self.replace(service);
+                log::info!("reloaded the configuration");
+            },
+            Err(error) => log::error!(
+                "could not reload the configuration: {}", error),
+        }
+    }
+}
+
+impl Service<Request<Body>> for ReloadableService {
+    type Response = Response<Body>;
+    type Error = ProxyError;
+    type Future = ServiceFuture;
+
+    fn poll_ready(&mut self, _context: &mut Context<'_>) ->
+        Poll<Result<(), Self::Error>>
+    { Ok(()).into() }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let service = self.current.read().unwrap().clone();
+        service.respond(request, self.connection)
+    }
+}
+
+// Reload the configuration whenever the process receives SIGHUP.
+#[cfg(unix)]
+pub async fn reload_on_hangup(service: ReloadableService) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(error) => {
+            log::warn!("could not listen for SIGHUP: {}", error);
+            return;
+        },
+    };
+
+    while hangups.recv().await.is_some() {
+        service.reload();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::Path;
+
+    use hyper::body::Bytes;
+
+    use crate::StaticRoute;
+
+    fn service(root: &Path) -> DevProxService {
+        DevProxService::new(vec![root.to_path_buf()])
+    }
+
+    async fn body(response: ServiceFuture) -> Bytes {
+        let response = response.await.unwrap();
+        hyper::body::to_bytes(response.into_body()).await.unwrap()
+    }
+
+    fn get(path: &str) -> Request<Body> {
+        Request::get(path).body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn replacing_the_service_affects_the_next_request() {
+        let old = tempfile::tempdir().unwrap();
+        let new = tempfile::tempdir().unwrap();
+        std::fs::write(old.path().join("page.html"), "old").unwrap();
+        std::fs::write(new.path().join("page.html"), "new").unwrap();
+        let mut reloadable = ReloadableService::new(service(old.path()));
+        // Connections each have a copy, which sees the replacement too.
+        let mut connection = reloadable.clone();
+
+        let dispatched = connection.call(get("/page.html"));
+        let mut replacement = service(new.path());
+        replacement.mount(StaticRoute::new(
+            "/docs".to_string(), vec![old.path().to_path_buf()]));
+        reloadable.replace(replacement);
+
+        assert_eq!(body(dispatched).await, "old");
+        assert_eq!(body(connection.call(get("/page.html"))).await, "new");
+        assert_eq!(body(reloadable.call(get("/docs/page.html"))).await,
+                   "old");
+    }
+
+    #[tokio::test]
+    async fn metrics_carry_over_to_the_replacement() {
+        let root = tempfile::tempdir().unwrap();
+        let mut old = service(root.path());
+        old.metrics();
+        let reloadable = ReloadableService::new(old);
+        let counted = reloadable.current.read().unwrap().metrics.clone();
+
+        let mut new = service(root.path());
+        new.metrics();
+        reloadable.replace(new);
+        let current = reloadable.current.read().unwrap().metrics.clone();
+        assert!(Arc::ptr_eq(&counted.unwrap(), &current.unwrap()));
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
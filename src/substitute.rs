@@ -0,0 +1,135 @@
+///////////////////////////////////////////////////////////////////////////////
+// NAME:            substitute.rs
+//
+// AUTHOR:          Ethan D. Twardy <ethan.twardy@gmail.com>
+//
+// DESCRIPTION:     Substitution of environment variables into static files.
+//
+// CREATED:         10/15/2026
+//
+// LAST EDITED:     10/15/2026
+////
+
+use std::collections::HashMap;
+use std::env;
+use std::path::Path;
+
+use crate::cache_control::pattern_matches;
+
+// Only variables whose names begin with this can be substituted, unless
+// configured otherwise, so that the rest of the environment isn't exposed.
+pub const DEFAULT_PREFIX: &str = "DEV_PROXY_";
+
+// Only textual files are substituted into. Anything else is served as is.
+fn is_text(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+    essence.starts_with("text/")
+        || matches!(essence, "application/javascript" | "application/json"
+                    | "application/xml" | "image/svg+xml")
+}
+
+#[derive(Clone)]
+pub struct Substitutions {
+    // Globs of the files to substitute into, as for Cache-Control rules.
+    patterns: Vec<String>,
+    // The values of the variables that may be substituted, by name.
+    variables: HashMap<String, String>,
+}
+
+impl Substitutions {
+    // Take the variables beginning with `prefix` from the environment. They
+    // are read once, since the environment of the process doesn't change.
+    pub fn from_env(patterns: Vec<String>, prefix: &str) -> Self {
+        let variables = env::vars()
+            .filter(|(name, _)| name.starts_with(prefix))
+            .collect();
+        Self { patterns, variables }
+    }
+
+    // Determine whether the file at `path`, relative to the root, is
+    // substituted into.
+    pub fn applies(&self, path: &Path, content_type: &str) -> bool {
+        is_text(content_type)
+            && self.patterns.iter()
+                .any(|pattern| pattern_matches(pattern, path))
+    }
+
+    // Replace each placeholder like %DEV_PROXY_API_URL% in `text` with the
+    // value of the variable it names. Placeholders for variables that aren't
+    // set (or can't be substituted) are left alone.
+    pub fn substitute(&self, text: &[u8]) -> Vec<u8> {
+        let mut substituted = Vec::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.iter().position(|&byte| byte == b'%') {
+            substituted.extend_from_slice(&rest[..start]);
+            rest = &rest[start..];
+            let value = rest[1..].iter().position(|&byte| byte == b'%')
+                .and_then(|end| {
+                    let name = std::str::from_utf8(&rest[1..end + 1]).ok()?;
+                    Some((self.variables.get(name)?, end + 2))
+                });
+            match value {
+                Some((value, length)) => {
+                    substituted.extend_from_slice(value.as_bytes());
+                    rest = &rest[length..];
+                },
+                // The closing '%' may begin another placeholder.
+                None => {
+                    substituted.push(b'%');
+                    rest = &rest[1..];
+                },
+            }
+        }
+
+        substituted.extend_from_slice(rest);
+        substituted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn substitutions(variables: &[(&str, &str)]) -> Substitutions {
+        Substitutions {
+            patterns: vec!["config.js".to_string()],
+            variables: variables.iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn placeholders_are_replaced_by_their_values() {
+        let substitutions = substitutions(&[("DEV_PROXY_API_URL", "/api")]);
+        let text = b"url = '%DEV_PROXY_API_URL%'; unset = '%DEV_PROXY_UNSET%'";
+        assert_eq!(substitutions.substitute(text),
+                   b"url = '/api'; unset = '%DEV_PROXY_UNSET%'");
+        assert_eq!(substitutions.substitute(b"100% %DEV_PROXY_API_URL%"),
+                   b"100% /api");
+    }
+
+    #[test]
+    fn only_matching_text_files_apply() {
+        let substitutions = substitutions(&[]);
+        assert!(substitutions.applies(Path::new("config.js"),
+                                      "application/javascript"));
+        assert!(!substitutions.applies(Path::new("app.js"),
+                                       "application/javascript"));
+        assert!(!substitutions.applies(Path::new("config.js"), "image/png"));
+    }
+
+    #[test]
+    fn only_variables_with_the_prefix_are_taken() {
+        env::set_var("DEV_PROXY_TEST_SUBSTITUTE_TAKEN", "yes");
+        env::set_var("OTHER_TEST_SUBSTITUTE_SECRET", "no");
+        let substitutions = Substitutions::from_env(
+            Vec::new(), "DEV_PROXY_TEST_SUBSTITUTE_");
+        let text = b"%DEV_PROXY_TEST_SUBSTITUTE_TAKEN% \
+                     %OTHER_TEST_SUBSTITUTE_SECRET%";
+        assert_eq!(substitutions.substitute(text),
+                   b"yes %OTHER_TEST_SUBSTITUTE_SECRET%");
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
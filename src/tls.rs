@@ -0,0 +1,551 @@
+///////////////////////////////////////////////////////////////////////////////
+// NAME:            tls.rs
+//
+// AUTHOR:          Ethan D. Twardy <ethan.twardy@gmail.com>
+//
+// DESCRIPTION:     Serving https, for testing features that need a secure
+//                  context, and verifying upstreams that use https.
+//
+// CREATED:         10/15/2026
+//
+// LAST EDITED:     10/15/2026
+////
+
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use hyper::server::conn::Http;
+use rustls_pemfile::Item;
+use tokio::net::TcpListener;
+use tokio_rustls::{
+    TlsAcceptor,
+    rustls::{
+        Certificate, ClientConfig, PrivateKey, RootCertStore, ServerConfig,
+        ServerName, SignatureScheme,
+        client::{ServerCertVerified, ServerCertVerifier},
+        sign,
+    },
+};
+
+use crate::config::{Config, ConfigError, RouteConfig};
+use crate::reload::ReloadableService;
+
+fn open(path: &Path) -> Result<BufReader<File>, ConfigError> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|error| ConfigError::Io(path.to_owned(), error))
+}
+
+fn load_certs(path: &Path) -> Result<Vec<Certificate>, ConfigError> {
+    let certs = rustls_pemfile::certs(&mut open(path)?)
+        .map_err(|error| ConfigError::Io(path.to_owned(), error))?;
+    if certs.is_empty() {
+        return Err(ConfigError::Tls(
+            format!("{}: no certificates found", path.display())));
+    }
+
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+// Load the first private key in the file, in any of the formats rustls reads.
+fn load_key(path: &Path) -> Result<PrivateKey, ConfigError> {
+    let mut reader = open(path)?;
+    loop {
+        match rustls_pemfile::read_one(&mut reader)
+            .map_err(|error| ConfigError::Io(path.to_owned(), error))?
+        {
+            Some(Item::RSAKey(key) | Item::PKCS8Key(key) | Item::ECKey(key)) =>
+                return Ok(PrivateKey(key)),
+            Some(_) => continue,
+            None => return Err(ConfigError::Tls(
+                format!("{}: no private key found", path.display()))),
+        }
+    }
+}
+
+// Build an acceptor from the certificate and key in the config, if there are
+// any. Only http/1.1 is offered, since that's all the server speaks.
+pub fn acceptor(config: &Config) -> Result<Option<TlsAcceptor>, ConfigError> {
+    let (cert, key) = match (&config.tls_cert, &config.tls_key) {
+        (Some(cert), Some(key)) => (cert, key),
+        (None, None) => return Ok(None),
+        _ => return Err(ConfigError::Tls(
+            "tls_cert and tls_key must be given together".to_string())),
+    };
+
+    let mut server_config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(load_certs(cert)?, load_key(key)?)
+        .map_err(|error| ConfigError::Tls(
+            format!("{}: {}", key.display(), error)))?;
+    server_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(Some(TlsAcceptor::from(Arc::new(server_config))))
+}
+
+// Accepts any certificate at all, for upstreams with self-signed ones.
+struct AcceptAnyCertificate;
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(&self, _end_entity: &Certificate,
+                          _intermediates: &[Certificate],
+                          _server_name: &ServerName,
+                          _scts: &mut dyn Iterator<Item = &[u8]>,
+                          _ocsp_response: &[u8], _now: SystemTime) ->
+        Result<ServerCertVerified, tokio_rustls::rustls::Error>
+    {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+// The certificates that upstreams are verified against: the platform's
+// roots, unless they're disabled, and those in the route's CA file.
+fn load_roots(route: &RouteConfig) -> Result<RootCertStore, ConfigError> {
+    let mut roots = RootCertStore::empty();
+    if route.system_roots {
+        let certs = rustls_native_certs::load_native_certs()
+            .map_err(|error| ConfigError::Tls(
+                format!("could not load the system's roots: {}", error)))?;
+        for cert in certs {
+            // Some platforms have roots that rustls can't parse.
+            let _ = roots.add(&Certificate(cert.0));
+        }
+    }
+
+    if let Some(path) = &route.ca_file {
+        for cert in load_certs(path)? {
+            roots.add(&cert).map_err(|error| ConfigError::Tls(
+                format!("{}: {}", path.display(), error)))?;
+        }
+    }
+
+    Ok(roots)
+}
+
+// The algorithms that a client key can be checked against its certificate
+// with, and how the certificate verifies each.
+const KEY_CHECKS: &[(SignatureScheme, &webpki::SignatureAlgorithm)] = &[
+    (SignatureScheme::ECDSA_NISTP256_SHA256, &webpki::ECDSA_P256_SHA256),
+    (SignatureScheme::ECDSA_NISTP384_SHA384, &webpki::ECDSA_P384_SHA384),
+    (SignatureScheme::ED25519, &webpki::ED25519),
+    (SignatureScheme::RSA_PSS_SHA256,
+     &webpki::RSA_PSS_2048_8192_SHA256_LEGACY_KEY),
+];
+
+// Check that `key` belongs to `cert`, by signing something with the one and
+// verifying it with the other. rustls would only find out in the handshake.
+fn check_key(cert: &Certificate, key: &PrivateKey) -> Result<(), String> {
+    let schemes: Vec<_> = KEY_CHECKS.iter().map(|(scheme, _)| *scheme)
+        .collect();
+    let signer = sign::any_supported_type(key)
+        .map_err(|error| error.to_string())?
+        .choose_scheme(&schemes)
+        .ok_or("unsupported type of key")?;
+    let message = b"dev-prox client key check";
+    let signature = signer.sign(message)
+        .map_err(|error| error.to_string())?;
+
+    let algorithm = KEY_CHECKS.iter()
+        .find(|(scheme, _)| *scheme == signer.scheme())
+        .map(|(_, algorithm)| *algorithm)
+        .ok_or("unsupported type of key")?;
+    webpki::EndEntityCert::try_from(cert.0.as_slice())
+        .and_then(|cert| cert.verify_signature(algorithm, message, &signature))
+        .map_err(|_| "the key doesn't match the certificate".to_string())
+}
+
+// Load the certificate and key that a route presents to its upstreams.
+fn load_identity(cert: &Path, key: &Path) ->
+    Result<(Vec<Certificate>, PrivateKey), ConfigError>
+{
+    let certs = load_certs(cert)?;
+    let private_key = load_key(key)?;
+    check_key(&certs[0], &private_key).map_err(|error| ConfigError::Tls(
+        format!("{}: {}", key.display(), error)))?;
+    Ok((certs, private_key))
+}
+
+// Build the configuration for connecting to the route's upstreams, if it
+// differs from the usual, so that a bad CA file is caught at startup. The
+// connector offers the protocols it speaks, so none are set here.
+pub fn client_config(route: &RouteConfig) ->
+    Result<Option<ClientConfig>, ConfigError>
+{
+    let identity = match (&route.client_cert, &route.client_key) {
+        (Some(cert), Some(key)) => Some(load_identity(cert, key)?),
+        (None, None) => None,
+        _ => return Err(ConfigError::Tls(
+            "client_cert and client_key must be given together".to_string())),
+    };
+    if route.ca_file.is_none() && route.system_roots
+        && !route.danger_accept_invalid_certs && identity.is_none()
+    {
+        return Ok(None);
+    }
+
+    let builder = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(load_roots(route)?);
+    let mut client_config = match identity {
+        Some((certs, key)) => builder.with_client_auth_cert(certs, key)
+            .map_err(|error| ConfigError::Tls(error.to_string()))?,
+        None => builder.with_no_client_auth(),
+    };
+    if route.danger_accept_invalid_certs {
+        client_config.dangerous()
+            .set_certificate_verifier(Arc::new(AcceptAnyCertificate));
+    }
+    Ok(Some(client_config))
+}
+
+// Accept connections on `bind`, and serve each over TLS. A failed handshake
+// only affects its own connection.
+pub async fn serve(bind: SocketAddr, acceptor: TlsAcceptor,
+                   service: ReloadableService) -> io::Result<()>
+{
+    let listener = TcpListener::bind(bind).await?;
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(error) => {
+                crate::accept_backoff(error).await;
+                continue;
+            },
+        };
+
+        let acceptor = acceptor.clone();
+        let mut service = service.clone();
+        service.peer(peer);
+        service.secure();
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(error) => {
+                    log::warn!("TLS handshake with {} failed: {}",
+                               peer, error);
+                    return;
+                },
+            };
+
+            let connection = Http::new()
+                .http1_only(true)
+                .serve_connection(stream, service)
+                .with_upgrades();
+            if let Err(error) = connection.await {
+                log::warn!("connection with {} failed: {}", peer, error);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core::convert::Infallible;
+    use core::future::ready;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use hyper::{Body, Request, Response, StatusCode, body::Bytes};
+    use tokio::time;
+
+    use crate::{Connection, DevProxService, ProxyRoute};
+
+    // A self-signed certificate for localhost.
+    fn self_signed() -> rcgen::Certificate {
+        rcgen::generate_simple_self_signed(vec!["localhost".to_string()])
+            .unwrap()
+    }
+
+    fn identity(cert: &rcgen::Certificate) -> (Vec<Certificate>, PrivateKey) {
+        (vec![Certificate(cert.serialize_der().unwrap())],
+         PrivateKey(cert.serialize_private_key_der()))
+    }
+
+    fn server_config(cert: &rcgen::Certificate) -> ServerConfig {
+        let (certs, key) = identity(cert);
+        ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .unwrap()
+    }
+
+    // A client that trusts `cert` alone.
+    fn trusting(cert: &rcgen::Certificate) -> ClientConfig {
+        let mut roots = RootCertStore::empty();
+        roots.add(&identity(cert).0[0]).unwrap();
+        ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth()
+    }
+
+    // Serve https with `server_config` on a port of its own, answering every
+    // request with the server name the client asked for. Returns the port.
+    async fn https_upstream(server_config: ServerConfig) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let acceptor = TlsAcceptor::from(Arc::new(server_config));
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    let stream = match acceptor.accept(stream).await {
+                        Ok(stream) => stream,
+                        Err(_) => return,
+                    };
+                    let name = stream.get_ref().1.server_name()
+                        .unwrap_or_default().to_string();
+                    let service = hyper::service::service_fn(move |_| ready(
+                        Ok::<_, Infallible>(Response::new(
+                            Body::from(name.clone())))));
+                    let _ = Http::new().serve_connection(stream, service)
+                        .await;
+                });
+            }
+        });
+        port
+    }
+
+    // Send a request for `path` through a service proxying to `route`.
+    async fn proxy(route: ProxyRoute, path: &str) -> (StatusCode, Bytes) {
+        let mut service = DevProxService::new(vec![PathBuf::from(".")]);
+        service.proxy(route);
+        let request = Request::get(path).body(Body::empty()).unwrap();
+        let response = Arc::new(service)
+            .respond(request, Connection::default()).await.unwrap();
+        let status = response.status();
+        (status, hyper::body::to_bytes(response.into_body()).await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn https_upstreams_are_proxied() {
+        let cert = self_signed();
+        let port = https_upstream(server_config(&cert)).await;
+        let upstream = format!("https://localhost:{}", port).parse().unwrap();
+        let mut route = ProxyRoute::new("/api".to_string(), vec![upstream]);
+        route.tls(trusting(&cert));
+        assert_eq!(proxy(route, "/api/ping").await,
+                   (StatusCode::OK, Bytes::from("localhost")));
+    }
+
+    // Write the certificate and key of `cert` into `directory`, returning
+    // their paths.
+    fn write_identity(cert: &rcgen::Certificate, directory: &Path) ->
+        (PathBuf, PathBuf)
+    {
+        let cert_path = directory.join("cert.pem");
+        let key_path = directory.join("key.pem");
+        std::fs::write(&cert_path, cert.serialize_pem().unwrap()).unwrap();
+        std::fs::write(&key_path, cert.serialize_private_key_pem()).unwrap();
+        (cert_path, key_path)
+    }
+
+    #[tokio::test]
+    async fn files_are_served_over_tls() {
+        let cert = self_signed();
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("index.html"), "secure").unwrap();
+        let (tls_cert, tls_key) = write_identity(&cert, root.path());
+        let config = Config {
+            tls_cert: Some(tls_cert), tls_key: Some(tls_key),
+            ..Config::default()
+        };
+        let acceptor = acceptor(&config).unwrap().unwrap();
+
+        let bind = std::net::TcpListener::bind("127.0.0.1:0").unwrap()
+            .local_addr().unwrap();
+        let service = ReloadableService::new(
+            DevProxService::new(vec![root.path().to_path_buf()]));
+        tokio::spawn(serve(bind, acceptor, service));
+        time::sleep(Duration::from_millis(100)).await;
+
+        let connector = hyper_rustls::HttpsConnectorBuilder::new()
+            .with_tls_config(trusting(&cert))
+            .https_only()
+            .enable_http1()
+            .build();
+        let client = hyper::Client::builder().build::<_, Body>(connector);
+        let uri = format!("https://localhost:{}/", bind.port())
+            .parse().unwrap();
+        let response = client.get(uri).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(hyper::body::to_bytes(response.into_body()).await.unwrap(),
+                   "secure");
+    }
+
+    #[test]
+    fn plain_http_is_served_without_a_certificate() {
+        assert!(acceptor(&Config::default()).unwrap().is_none());
+        let config = Config {
+            tls_cert: Some(PathBuf::from("cert.pem")), ..Config::default()
+        };
+        assert!(matches!(acceptor(&config), Err(ConfigError::Tls(_))));
+    }
+
+    #[tokio::test]
+    async fn http_upstreams_dont_attempt_a_handshake() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream = format!("http://{}", listener.local_addr().unwrap())
+            .parse().unwrap();
+        let first_bytes = tokio::spawn(async move {
+            use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0; 4];
+            stream.read_exact(&mut request).await.unwrap();
+            stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").await
+                .unwrap();
+            request
+        });
+
+        let route = ProxyRoute::new("/api".to_string(), vec![upstream]);
+        assert_eq!(proxy(route, "/api/ping").await.0, StatusCode::NO_CONTENT);
+        assert_eq!(&first_bytes.await.unwrap(), b"GET ");
+    }
+
+    #[tokio::test]
+    async fn https_upstreams_are_verified() {
+        let port = https_upstream(server_config(&self_signed())).await;
+        let upstream = format!("https://localhost:{}", port).parse().unwrap();
+        let mut route = ProxyRoute::new("/api".to_string(), vec![upstream]);
+        route.tls(trusting(&self_signed()));
+        assert_eq!(proxy(route, "/api/ping").await.0,
+                   StatusCode::BAD_GATEWAY);
+    }
+
+    // A route to `upstream`, with the rest of its settings in TOML.
+    fn route_config(upstream: &str, settings: &str) -> RouteConfig {
+        toml::from_str(&format!("prefix = \"/api\"\nupstream = \"{}\"\n{}",
+                                upstream, settings)).unwrap()
+    }
+
+    // A route to `upstream` with the TLS settings of `config`.
+    fn configured_route(upstream: &str, config: &RouteConfig) -> ProxyRoute {
+        let mut route = ProxyRoute::new(
+            "/api".to_string(), vec![upstream.parse().unwrap()]);
+        if let Some(client_config) = client_config(config).unwrap() {
+            route.tls(client_config);
+        }
+        route
+    }
+
+    #[tokio::test]
+    async fn upstreams_can_be_trusted_by_ca_or_unconditionally() {
+        let cert = self_signed();
+        let directory = tempfile::tempdir().unwrap();
+        let (ca_file, _) = write_identity(&cert, directory.path());
+        let port = https_upstream(server_config(&cert)).await;
+        let upstream = format!("https://localhost:{}", port);
+
+        let trusted = route_config(&upstream, &format!(
+            "ca_file = {:?}\nsystem_roots = false", ca_file));
+        let anything = route_config(
+            &upstream, "danger_accept_invalid_certs = true");
+        let defaults = route_config(&upstream, "");
+        assert!(client_config(&defaults).unwrap().is_none());
+
+        for config in [trusted, anything] {
+            let route = configured_route(&upstream, &config);
+            assert_eq!(proxy(route, "/api/ping").await.0, StatusCode::OK);
+        }
+        let route = configured_route(&upstream, &defaults);
+        assert_eq!(proxy(route, "/api/ping").await.0,
+                   StatusCode::BAD_GATEWAY);
+    }
+
+    #[test]
+    fn bad_ca_files_name_the_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let empty = directory.path().join("empty.pem");
+        std::fs::write(&empty, "").unwrap();
+        let missing = directory.path().join("missing.pem");
+        for path in [empty, missing] {
+            let config = route_config(
+                "https://localhost", &format!("ca_file = {:?}", path));
+            let error = client_config(&config).err().unwrap();
+            assert!(error.to_string().contains(path.to_str().unwrap()),
+                    "{}", error);
+        }
+    }
+
+    // A certificate authority for clients, and a client certificate it
+    // signed, written into `directory`. Returns the authority, and the paths
+    // of the client's certificate and key.
+    fn client_identity(directory: &Path) ->
+        (rcgen::Certificate, PathBuf, PathBuf)
+    {
+        let mut params = rcgen::CertificateParams::new(Vec::new());
+        params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let ca = rcgen::Certificate::from_params(params).unwrap();
+        let client = rcgen::Certificate::from_params(
+            rcgen::CertificateParams::new(vec!["client".to_string()]))
+            .unwrap();
+        let cert_path = directory.join("client.pem");
+        let key_path = directory.join("client.key");
+        std::fs::write(&cert_path, client.serialize_pem_with_signer(&ca)
+                       .unwrap()).unwrap();
+        std::fs::write(&key_path, client.serialize_private_key_pem())
+            .unwrap();
+        (ca, cert_path, key_path)
+    }
+
+    #[tokio::test]
+    async fn client_certificates_are_presented_to_upstreams() {
+        use tokio_rustls::rustls::server::AllowAnyAuthenticatedClient;
+
+        let directory = tempfile::tempdir().unwrap();
+        let cert = self_signed();
+        let (ca_file, _) = write_identity(&cert, directory.path());
+        let (client_ca, client_cert, client_key) =
+            client_identity(directory.path());
+        let mut clients = RootCertStore::empty();
+        clients.add(&identity(&client_ca).0[0]).unwrap();
+        let (certs, key) = identity(&cert);
+        let server_config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_client_cert_verifier(
+                AllowAnyAuthenticatedClient::new(clients).boxed())
+            .with_single_cert(certs, key)
+            .unwrap();
+        let port = https_upstream(server_config).await;
+        let upstream = format!("https://localhost:{}", port);
+
+        let trusted = format!("ca_file = {:?}\nsystem_roots = false\n",
+                              ca_file);
+        let mutual = route_config(&upstream, &format!(
+            "{}client_cert = {:?}\nclient_key = {:?}", trusted, client_cert,
+            client_key));
+        let route = configured_route(&upstream, &mutual);
+        assert_eq!(proxy(route, "/api/ping").await.0, StatusCode::OK);
+
+        let anonymous = route_config(&upstream, &trusted);
+        let route = configured_route(&upstream, &anonymous);
+        assert_eq!(proxy(route, "/api/ping").await.0,
+                   StatusCode::BAD_GATEWAY);
+    }
+
+    #[test]
+    fn mismatched_client_keys_are_refused() {
+        let directory = tempfile::tempdir().unwrap();
+        let (_, client_cert, _) = client_identity(directory.path());
+        let (_, other_key) = write_identity(&self_signed(), directory.path());
+        let config = route_config("https://localhost", &format!(
+            "client_cert = {:?}\nclient_key = {:?}", client_cert, other_key));
+        let error = client_config(&config).err().unwrap();
+        assert!(error.to_string().contains(other_key.to_str().unwrap()),
+                "{}", error);
+
+        let config = route_config("https://localhost", &format!(
+            "client_cert = {:?}", client_cert));
+        assert!(client_config(&config).is_err());
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
@@ -0,0 +1,120 @@
+///////////////////////////////////////////////////////////////////////////////
+// NAME:            unix.rs
+//
+// AUTHOR:          Ethan D. Twardy <ethan.twardy@gmail.com>
+//
+// DESCRIPTION:     Serving http on a Unix domain socket, behind another
+//                  server.
+//
+// CREATED:         10/15/2026
+//
+// LAST EDITED:     10/15/2026
+////
+
+use std::io;
+use std::os::unix::fs::FileTypeExt;
+use std::path::Path;
+
+use hyper::server::conn::Http;
+use tokio::{fs, net::UnixListener};
+
+use crate::reload::ReloadableService;
+
+// A socket left behind by an earlier run would keep the listener from
+// binding, so it's removed. Anything else at the path is left alone.
+async fn remove_stale(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path).await {
+        Ok(metadata) if metadata.file_type().is_socket() =>
+            fs::remove_file(path).await,
+        Ok(_) => Err(io::ErrorKind::AlreadyExists.into()),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error),
+    }
+}
+
+// Accept connections on the socket at `path`, and serve each. There's no
+// address for the peer, so requests aren't given an X-Forwarded-For.
+pub async fn serve(path: &Path, service: ReloadableService) ->
+    io::Result<()>
+{
+    remove_stale(path).await?;
+    let listener = UnixListener::bind(path)?;
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(error) => {
+                crate::accept_backoff(error).await;
+                continue;
+            },
+        };
+
+        let service = service.clone();
+        tokio::spawn(async move {
+            let connection = Http::new()
+                .http1_only(true)
+                .serve_connection(stream, service)
+                .with_upgrades();
+            if let Err(error) = connection.await {
+                log::warn!("connection failed: {}", error);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use hyper::{Body, Request, StatusCode};
+    use tokio::{net::UnixStream, time};
+
+    use crate::DevProxService;
+
+    // Wait for a connection to the socket at `path` to be accepted.
+    async fn connect(path: &Path) -> UnixStream {
+        for _ in 0..100 {
+            if let Ok(stream) = UnixStream::connect(path).await {
+                return stream;
+            }
+            time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("nothing is listening on {}", path.display());
+    }
+
+    #[tokio::test]
+    async fn files_are_served_over_the_socket() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(root.path().join("index.html"), "over unix").unwrap();
+        let path = root.path().join(".dev-proxy.sock");
+        // A socket left by an earlier run.
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        let service = ReloadableService::new(
+            DevProxService::new(vec![root.path().to_path_buf()]));
+        let socket = path.clone();
+        tokio::spawn(async move { serve(&socket, service).await });
+
+        let (mut sender, connection) = hyper::client::conn::handshake(
+            connect(&path).await).await.unwrap();
+        tokio::spawn(connection);
+        let request = Request::get("/").header("host", "localhost")
+            .body(Body::empty()).unwrap();
+        let response = sender.send_request(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(hyper::body::to_bytes(response.into_body()).await.unwrap(),
+                   "over unix");
+    }
+
+    #[tokio::test]
+    async fn other_files_are_not_removed() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().join("not-a-socket");
+        std::fs::write(&path, "keep").unwrap();
+        let error = remove_stale(&path).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read(&path).unwrap(), b"keep");
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////
@@ -0,0 +1,241 @@
+///////////////////////////////////////////////////////////////////////////////
+// NAME:            write.rs
+//
+// AUTHOR:          Ethan D. Twardy <ethan.twardy@gmail.com>
+//
+// DESCRIPTION:     Uploading and deleting static files, when allowed.
+//
+// CREATED:         10/15/2026
+//
+// LAST EDITED:     10/15/2026
+////
+
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use hyper::{Body, Request, StatusCode, body::HttpBody};
+use tokio::{fs, io::AsyncWriteExt};
+
+use crate::{
+    empty_response, is_hidden, relative_path, resolve, ResponseResult,
+    StaticFileOptions,
+};
+
+// The largest body accepted for an upload, unless configured otherwise.
+pub const DEFAULT_MAX_UPLOAD_SIZE: u64 = 64 * 1024 * 1024;
+
+// Find the file named by a decoded URL path, relative to the root. The same
+// paths are refused as for reading, and directories can't be written. Hidden
+// files are as missing as they are to GET.
+fn target(path: &str, options: &StaticFileOptions) -> io::Result<PathBuf> {
+    let relative = relative_path(path)?;
+    if !options.allow_hidden && is_hidden(&relative) {
+        return Err(io::ErrorKind::NotFound.into());
+    }
+
+    match relative.as_os_str().is_empty() || path.ends_with('/') {
+        true => Err(io::ErrorKind::InvalidInput.into()),
+        false => Ok(relative),
+    }
+}
+
+// Create the directories above `target` that don't exist yet. The deepest one
+// that does is resolved first, so that none are created outside of the root
+// by way of a symlink.
+async fn create_parents(root: &Path, target: &Path,
+                        options: &StaticFileOptions) -> io::Result<()>
+{
+    let parent = target.parent().unwrap_or(root);
+    let mut existing = parent;
+    while fs::symlink_metadata(existing).await.is_err() {
+        existing = existing.parent().unwrap_or(root);
+    }
+
+    let resolved = resolve(root, existing, options.symlinks).await?;
+    if !fs::metadata(&resolved).await?.is_dir() {
+        return Err(io::ErrorKind::AlreadyExists.into());
+    }
+
+    fs::create_dir_all(parent).await
+}
+
+// Write `body` to `file`, failing if it's longer than `limit`. Returns whether
+// it fit.
+async fn write_body(mut file: fs::File, mut body: Body, limit: u64) ->
+    io::Result<bool>
+{
+    let mut written = 0;
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(io::Error::other)?;
+        written += chunk.len() as u64;
+        if written > limit {
+            return Ok(false);
+        }
+        file.write_all(&chunk).await?;
+    }
+
+    file.flush().await?;
+    Ok(true)
+}
+
+// Counts uploads, to give each one's partial file a name of its own.
+static UPLOADS: AtomicUsize = AtomicUsize::new(0);
+
+// A hidden file next to `target` for an upload to it to be written to. The
+// name is unique to this process and upload, so that uploads to the same
+// target, even from another server on the same root, don't write to the same
+// file.
+fn partial_path(target: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(target.file_name().unwrap_or_default());
+    let upload = UPLOADS.fetch_add(1, Ordering::Relaxed);
+    name.push(format!(".{}.{}.upload", process::id(), upload));
+    target.with_file_name(name)
+}
+
+// Write the upload to a hidden file next to the target, and move it into
+// place once it's complete, so that a failed upload leaves the target alone.
+async fn upload(root: &Path, relative: &Path, request: Request<Body>,
+                options: &StaticFileOptions) -> io::Result<StatusCode>
+{
+    let limit = options.max_upload_size;
+    let length = request.headers().get("content-length")
+        .and_then(|length| length.to_str().ok())
+        .and_then(|length| length.parse::<u64>().ok());
+    if length.is_some_and(|length| length > limit) {
+        return Ok(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    let target = root.join(relative);
+    let existed = match fs::symlink_metadata(&target).await {
+        Ok(metadata) if metadata.is_dir() => return Ok(StatusCode::CONFLICT),
+        Ok(_) => {
+            resolve(root, &target, options.symlinks).await?;
+            true
+        },
+        Err(error) if error.kind() == io::ErrorKind::NotFound => false,
+        Err(error) => return Err(error),
+    };
+
+    create_parents(root, &target, options).await?;
+    // A file that's already there belongs to someone else, so it's never
+    // written over, or removed.
+    let partial = partial_path(&target);
+    let file = fs::OpenOptions::new().write(true).create_new(true)
+        .open(&partial).await?;
+    match write_body(file, request.into_body(), limit).await {
+        Ok(true) => {},
+        Ok(false) => {
+            let _ = fs::remove_file(&partial).await;
+            return Ok(StatusCode::PAYLOAD_TOO_LARGE);
+        },
+        Err(error) => {
+            let _ = fs::remove_file(&partial).await;
+            return Err(error);
+        },
+    }
+
+    fs::rename(&partial, &target).await?;
+    match existed {
+        true => Ok(StatusCode::NO_CONTENT),
+        false => Ok(StatusCode::CREATED),
+    }
+}
+
+async fn remove(root: &Path, relative: &Path, options: &StaticFileOptions) ->
+    io::Result<StatusCode>
+{
+    let target = root.join(relative);
+    resolve(root, &target, options.symlinks).await?;
+    if fs::symlink_metadata(&target).await?.is_dir() {
+        return Ok(StatusCode::CONFLICT);
+    }
+
+    fs::remove_file(&target).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Translate the outcome of a write into a response.
+fn respond(result: io::Result<StatusCode>, options: &StaticFileOptions) ->
+    ResponseResult
+{
+    use io::ErrorKind::*;
+    match result {
+        Ok(status) => Ok(empty_response(status)),
+        Err(error) => match error.kind() {
+            NotFound => Ok(empty_response(StatusCode::NOT_FOUND)),
+            PermissionDenied => Ok(options.denied()),
+            InvalidInput => Ok(empty_response(StatusCode::METHOD_NOT_ALLOWED)),
+            AlreadyExists => Ok(empty_response(StatusCode::CONFLICT)),
+            _ => Err(error.into()),
+        },
+    }
+}
+
+// Store the body of a PUT request as the file at `path`, under `root`.
+// Missing directories are created. Responds 201 for a new file, and 204 for
+// one that was replaced.
+pub async fn put(root: PathBuf, path: String, request: Request<Body>,
+                 options: Arc<StaticFileOptions>) -> ResponseResult
+{
+    let root = fs::canonicalize(&root).await?;
+    let result = match target(&path, &options) {
+        Ok(relative) => upload(&root, &relative, request, &options).await,
+        Err(error) => Err(error),
+    };
+    respond(result, &options)
+}
+
+// Delete the file at `path`, under `root`. Directories are left alone.
+pub async fn delete(root: PathBuf, path: String,
+                    options: Arc<StaticFileOptions>) -> ResponseResult
+{
+    let root = fs::canonicalize(&root).await?;
+    let result = match target(&path, &options) {
+        Ok(relative) => remove(&root, &relative, &options).await,
+        Err(error) => Err(error),
+    };
+    respond(result, &options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn targets_are_files_under_the_root() {
+        let options = StaticFileOptions::default();
+        assert_eq!(target("/uploads/foo.png", &options).unwrap(),
+                   Path::new("uploads/foo.png"));
+
+        let refused = [
+            ("/", io::ErrorKind::InvalidInput),
+            ("/uploads/", io::ErrorKind::InvalidInput),
+            ("/../foo.png", io::ErrorKind::PermissionDenied),
+            ("/.env", io::ErrorKind::NotFound),
+            ("/.git/config", io::ErrorKind::NotFound),
+        ];
+        for (path, kind) in refused {
+            assert_eq!(target(path, &options).unwrap_err().kind(), kind,
+                       "{}", path);
+        }
+    }
+
+    #[test]
+    fn each_upload_gets_a_hidden_partial_file_of_its_own() {
+        let target = Path::new("/srv/uploads/foo.png");
+        let (first, second) = (partial_path(target), partial_path(target));
+        assert_ne!(first, second);
+        for partial in [first, second] {
+            assert_eq!(partial.parent(), target.parent());
+            let name = partial.file_name().unwrap().to_str().unwrap();
+            assert!(name.starts_with(".foo.png."), "{}", name);
+        }
+    }
+}
+
+///////////////////////////////////////////////////////////////////////////////